@@ -0,0 +1,165 @@
+// Copyright 2023-2024, Offchain Labs, Inc.
+// For licensing, see https://github.com/OffchainLabs/cargo-stylus/blob/main/licenses/COPYRIGHT.md
+
+use crate::util::color::Color;
+use crate::{check, AnalyzeConfig};
+use bytesize::ByteSize;
+use eyre::{bail, Result};
+use std::fmt;
+use wasmparser::{Parser, Payload, TypeRef};
+
+/// Static data beyond this many bytes is paid for in every deployment.
+const MAX_DATA_BYTES: usize = 4 * 1024;
+
+/// Memory pages a contract may declare up front before each call pays for more than it likely needs.
+const MAX_INITIAL_PAGES: u64 = 2;
+
+/// Function bodies beyond this many bytes usually point at heavy inlining or monomorphization.
+const MAX_FUNCTION_BYTES: usize = 4 * 1024;
+
+/// How serious an analysis finding is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Note,
+    Warning,
+}
+
+/// A likely cost or performance pitfall found in a contract's WASM.
+#[derive(Debug, PartialEq)]
+pub struct Finding {
+    pub severity: Severity,
+    /// Where in the module the finding is, e.g. `function 12`.
+    pub location: String,
+    pub message: String,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Note => "note".grey(),
+            Severity::Warning => "warning".yellow(),
+        };
+        write!(
+            f,
+            "{severity}: {}: {}",
+            self.location.lavender(),
+            self.message
+        )
+    }
+}
+
+/// Builds the contract and reports the pitfalls found in its WASM.
+pub async fn analyze(cfg: AnalyzeConfig) -> Result<()> {
+    let contract = check::check(&cfg.check_config).await?;
+    let findings = analyze_wasm(contract.code())?;
+    if findings.is_empty() {
+        println!("No findings");
+        return Ok(());
+    }
+    for finding in &findings {
+        println!("{finding}");
+    }
+    let warnings = findings
+        .iter()
+        .filter(|f| f.severity == Severity::Warning)
+        .count();
+    if cfg.deny && warnings > 0 {
+        bail!("{warnings} warnings denied");
+    }
+    Ok(())
+}
+
+/// Inspects a WASM for large data sections, excessive memory declarations, and oversized
+/// functions.
+pub fn analyze_wasm(wasm: &[u8]) -> Result<Vec<Finding>> {
+    let mut findings = vec![];
+    let mut data_bytes = 0;
+    let mut function_index = 0;
+    for payload in Parser::new(0).parse_all(wasm) {
+        match payload? {
+            Payload::ImportSection(reader) => {
+                for import in reader {
+                    if let TypeRef::Func(_) = import?.ty {
+                        function_index += 1;
+                    }
+                }
+            }
+            Payload::MemorySection(reader) => {
+                for (index, memory) in reader.into_iter().enumerate() {
+                    let memory = memory?;
+                    if memory.initial > MAX_INITIAL_PAGES {
+                        findings.push(Finding {
+                            severity: Severity::Warning,
+                            location: format!("memory {index}"),
+                            message: format!(
+                                "declares {} initial pages, more than {MAX_INITIAL_PAGES}",
+                                memory.initial
+                            ),
+                        });
+                    }
+                    if memory.maximum.is_none() {
+                        findings.push(Finding {
+                            severity: Severity::Note,
+                            location: format!("memory {index}"),
+                            message: "has no maximum, so it may grow without bound".into(),
+                        });
+                    }
+                }
+            }
+            Payload::DataSection(reader) => {
+                for data in reader {
+                    data_bytes += data?.data.len();
+                }
+            }
+            Payload::CodeSectionEntry(body) => {
+                let size = body.range().len();
+                if size > MAX_FUNCTION_BYTES {
+                    findings.push(Finding {
+                        severity: Severity::Warning,
+                        location: format!("function {function_index}"),
+                        message: format!("body is {}", ByteSize::b(size as u64)),
+                    });
+                }
+                function_index += 1;
+            }
+            _ => {}
+        }
+    }
+    if data_bytes > MAX_DATA_BYTES {
+        findings.push(Finding {
+            severity: Severity::Warning,
+            location: "data section".into(),
+            message: format!("holds {} of static data", ByteSize::b(data_bytes as u64)),
+        });
+    }
+    Ok(findings)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_analyze_wasm() -> Result<()> {
+        let wasm = wasmer::wat2wasm(b"(module (memory 1 1) (func))")?;
+        assert_eq!(analyze_wasm(&wasm)?, vec![]);
+
+        let data = "x".repeat(MAX_DATA_BYTES + 1);
+        let wat =
+            format!(r#"(module (import "e" "f" (func)) (memory 4) (data (i32.const 0) "{data}"))"#);
+        let wasm = wasmer::wat2wasm(wat.as_bytes())?;
+        let locations: Vec<_> = analyze_wasm(&wasm)?
+            .into_iter()
+            .map(|f| (f.severity, f.location))
+            .collect();
+        assert_eq!(
+            locations,
+            vec![
+                (Severity::Warning, "memory 0".to_string()),
+                (Severity::Note, "memory 0".to_string()),
+                (Severity::Warning, "data section".to_string()),
+            ]
+        );
+        Ok(())
+    }
+}
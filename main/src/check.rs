@@ -2,63 +2,229 @@
 // For licensing, see https://github.com/OffchainLabs/cargo-stylus/blob/main/licenses/COPYRIGHT.md
 
 use crate::{
-    constants::TOOLCHAIN_FILE_NAME,
+    compression::{self, Compression},
+    constants::{MAX_CODE_SIZE_BYTES, MAX_WASM_FUNCTIONS, TOOLCHAIN_FILE_NAME},
+    error::StylusError,
+    export_abi::{self},
     macros::*,
+    prelude::deployment_calldata,
     project::{self, extract_toolchain_channel, BuildConfig},
-    util::color::Color,
+    report::{CheckReport, Diagnostic, Rule},
+    sarif,
+    util::{color::Color, spinner::with_spinner, timer::StepTimer},
     CheckConfig,
-    export_abi::{self},
-};
-use ethers::{
-    types::{
-        U256 as EU256,
-    },
 };
 use alloy_primitives::U256;
-use bytesize::ByteSize;
 use eyre::{eyre, ErrReport, Result, WrapErr};
-use std::path::PathBuf;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
 
 /// Checks that a contract is valid and can be deployed onchain.
 /// Returns whether the WASM is already up-to-date and activated onchain, and the data fee.
 pub async fn check(cfg: &CheckConfig) -> Result<ContractCheck> {
+    let outcome = diagnose(cfg).await?;
+    if let Some(path) = &cfg.sarif {
+        sarif::write_sarif(path, &outcome.diagnostics)?;
+        greyln!("wrote check results to {}", path.display().lavender());
+    }
+    match outcome.contract {
+        Some(contract) => Ok(contract),
+        None => Err(StylusError::Validation(outcome.summary()).into()),
+    }
+}
+
+/// Runs the checks, collecting the ones the contract fails as diagnostics rather than
+/// bailing at the first. Errors are left for failures unrelated to the contract's validity,
+/// like a broken build.
+pub async fn diagnose(cfg: &CheckConfig) -> Result<CheckOutcome> {
     let verbose = cfg.common_cfg.verbose;
-    let (wasm, project_hash) = cfg.build_wasm().wrap_err("failed to build wasm")?;
+    let mut timer = StepTimer::start(verbose);
+    let (wasm, project_hash) = match &cfg.wasm_file {
+        Some(wasm) => (wasm.clone(), [0u8; 32]),
+        None => {
+            let toolchain_channel = match cfg.toolchain_channel() {
+                Ok(channel) => channel,
+                Err(e) => {
+                    let message = format!("{e:#}");
+                    let diagnostic = Diagnostic::new(Rule::Toolchain, message, TOOLCHAIN_FILE_NAME);
+                    return Ok(CheckOutcome::failed(vec![diagnostic]));
+                }
+            };
+            cfg.build_wasm(&toolchain_channel)
+                .map_err(|e| StylusError::Build(format!("{e:#}")))?
+        }
+    };
+    timer.lap("build");
 
     if verbose {
         greyln!("reading wasm file at {}", wasm.to_string_lossy().lavender());
     }
 
-    if let Err(e) = export_abi::export_abi(None, true) {
-        eprintln!("Error: {:?}", e);
+    // The ABI doesn't affect the size, so skip it when that's all that's printed. It would
+    // also land on stdout ahead of a JSON report, which must be the only thing there.
+    if !cfg.size_only && !cfg.common_cfg.json {
+        if let Err(e) = export_abi::export_abi(None, true, false) {
+            eprintln!("Error: {:?}", e);
+        }
     }
+    timer.lap("abi export");
 
-    let (wasm_file_bytes, code) =
-        project::compress_wasm(&wasm, project_hash).wrap_err("failed to compress WASM")?;
+    let compression = cfg.common_cfg.compression;
+    let embedded_hash = (!cfg.no_project_hash).then_some(project_hash);
+    let (wasm_file_bytes, code) = with_spinner("compressing wasm", cfg.common_cfg.quiet, || {
+        compression::compress_wasm(&wasm, embedded_hash, compression, cfg.strip)
+    })
+    .wrap_err("failed to compress WASM")?;
+    timer.lap("compression");
 
-    let init_code = contract_deployment_calldata(&code);
+    let max_code_size = cfg.max_code_size_bytes.unwrap_or(MAX_CODE_SIZE_BYTES);
+    let size_label = match compression {
+        Compression::None => "uncompressed wasm size",
+        _ => "compressed wasm size",
+    };
+    if !cfg.size_only {
+        greyln!(
+            "{size_label}: {}",
+            format_file_size(code.len(), max_code_size * 2 / 3, max_code_size)
+        );
+    }
+    // Diagnostics are reported against the prebuilt WASM, or the project's manifest.
+    let artifact = match &cfg.wasm_file {
+        Some(wasm) => wasm.to_string_lossy().into_owned(),
+        None => "Cargo.toml".to_string(),
+    };
+    let mut diagnostics = vec![];
+    if code.len() as u64 > max_code_size {
+        let msg = format!(
+            "{size_label} {} B exceeds the limit of {max_code_size} B",
+            code.len()
+        );
+        diagnostics.push(Diagnostic::new(Rule::CodeSize, msg, &artifact));
+    }
+
+    let max_functions = cfg.max_functions.unwrap_or(MAX_WASM_FUNCTIONS);
+    let functions = project::count_wasm_functions(&wasm_file_bytes)?;
+    if verbose {
+        greyln!("wasm function count: {}", functions.lavender());
+    }
+    if functions > max_functions {
+        let msg =
+            format!("wasm defines {functions} functions, exceeding the limit of {max_functions}");
+        diagnostics.push(Diagnostic::new(Rule::FunctionCount, msg, &artifact));
+    }
+    if !diagnostics.is_empty() {
+        return Ok(CheckOutcome::failed(diagnostics));
+    }
+
+    let init_code = deployment_calldata(&code, compression, cfg.raw_code)?;
+    let prelude = &init_code[..init_code.len() - code.len()];
+    write_artifact(cfg.wasm_out.as_deref(), &wasm_file_bytes, "processed wasm")?;
+    write_artifact(cfg.compressed_out.as_deref(), &code, "compressed wasm")?;
+    write_artifact(cfg.prelude_out.as_deref(), prelude, "deployment prelude")?;
     let deploy_code: String = init_code
         .iter()
         .map(|byte| format!("{:02x}", byte))
         .collect();
 
-    println!("DEPLOYMENT_CODE: {}", deploy_code);
+    match &cfg.calldata_out {
+        Some(path) => {
+            fs::write(path, &deploy_code).wrap_err("failed to write deployment calldata")?;
+            greyln!("wrote deployment calldata to {}", path.display().lavender());
+        }
+        None if cfg.size_only || cfg.common_cfg.json => {}
+        None => println!("DEPLOYMENT_CODE: {}", deploy_code),
+    }
+
+    if let Some(path) = &cfg.size_log {
+        let entry = SizeLogEntry {
+            timestamp: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+            project_hash: hex::encode(project_hash),
+            wasm_size: wasm_file_bytes.len(),
+            compressed_size: code.len(),
+        };
+        append_size_log(path, &entry).wrap_err("failed to append to size log")?;
+        if cfg.size_diff {
+            print_size_diff(path).wrap_err("failed to diff size log")?;
+        }
+    }
 
     if verbose {
-        greyln!("connecting to RPC: {}", &cfg.common_cfg.endpoint.lavender());
+        let endpoints = cfg.common_cfg.endpoint.join(", ");
+        greyln!("connecting to RPC: {}", endpoints.lavender());
     }
 
-    Ok(ContractCheck::Ready {
+    let fee = U256::from(0_u32);
+    let report = CheckReport {
+        project_hash: hex::encode(project_hash),
+        wasm_size: wasm_file_bytes.len(),
+        compressed_size: code.len(),
+        compression,
+        raw_code: cfg.raw_code,
+        data_fee_wei: fee.to_string(),
+        deployment_calldata: format!("0x{deploy_code}"),
+    };
+    let contract = ContractCheck::Ready {
         code: wasm_file_bytes,
-        fee: U256::from(0_u32),
+        compressed: code,
+        report,
+        fee,
+    };
+    Ok(CheckOutcome {
+        diagnostics,
+        contract: Some(contract),
     })
 }
 
+/// Writes the bytes of a deployment artifact to a file, if one was requested.
+fn write_artifact(path: Option<&Path>, bytes: &[u8], name: &str) -> Result<()> {
+    let Some(path) = path else {
+        return Ok(());
+    };
+    fs::write(path, bytes).wrap_err_with(|| eyre!("failed to write {name}"))?;
+    greyln!("wrote {name} to {}", path.display().lavender());
+    Ok(())
+}
+
+/// What `diagnose` found: the checks the contract failed, and the contract if it failed none.
+pub struct CheckOutcome {
+    pub diagnostics: Vec<Diagnostic>,
+    pub contract: Option<ContractCheck>,
+}
+
+impl CheckOutcome {
+    fn failed(diagnostics: Vec<Diagnostic>) -> Self {
+        Self {
+            diagnostics,
+            contract: None,
+        }
+    }
+
+    /// The messages of all the diagnostics, as a single line.
+    fn summary(&self) -> String {
+        let messages: Vec<_> = self
+            .diagnostics
+            .iter()
+            .map(|d| d.message.as_str())
+            .collect();
+        messages.join("; ")
+    }
+}
+
 /// Whether a contract is active, or needs activation.
 #[derive(PartialEq)]
 pub enum ContractCheck {
     /// Contract can be activated with the given data fee.
-    Ready { code: Vec<u8>, fee: U256 },
+    Ready {
+        code: Vec<u8>,
+        compressed: Vec<u8>,
+        report: CheckReport,
+        fee: U256,
+    },
 }
 
 impl ContractCheck {
@@ -67,6 +233,21 @@ impl ContractCheck {
             Self::Ready { code, .. } => code,
         }
     }
+    /// The code the prelude returns, compressed with the scheme in the report, which is
+    /// what ends up onchain.
+    pub fn compressed_code(&self) -> &[u8] {
+        match self {
+            Self::Ready { compressed, .. } => compressed,
+        }
+    }
+    pub fn report(&self) -> &CheckReport {
+        match self {
+            Self::Ready { report, .. } => report,
+        }
+    }
+    pub fn compressed_size(&self) -> usize {
+        self.report().compressed_size
+    }
     pub fn suggest_fee(&self) -> U256 {
         match self {
             Self::Ready { fee, .. } => *fee,
@@ -75,34 +256,106 @@ impl ContractCheck {
 }
 
 impl CheckConfig {
-    fn build_wasm(&self) -> Result<(PathBuf, [u8; 32])> {
-        if let Some(wasm) = self.wasm_file.clone() {
-            return Ok((wasm, [0u8; 32]));
-        }
+    /// The channel pinned by the project's toolchain file, once it's installed.
+    fn toolchain_channel(&self) -> Result<String> {
         let toolchain_file_path = PathBuf::from(".").as_path().join(TOOLCHAIN_FILE_NAME);
         let toolchain_channel = extract_toolchain_channel(&toolchain_file_path)?;
+        project::ensure_toolchain_installed(&toolchain_channel, self.auto_install_toolchain)?;
+        Ok(toolchain_channel)
+    }
+
+    fn build_wasm(&self, toolchain_channel: &str) -> Result<(PathBuf, [u8; 32])> {
         let rust_stable = !toolchain_channel.contains("nightly");
         let mut cfg = BuildConfig::new(rust_stable);
         cfg.features = self.common_cfg.features.clone();
-        let wasm = project::build_dylib(cfg.clone())?;
+        cfg.compression = self.common_cfg.compression;
+        cfg.profile = self.common_cfg.profile.clone();
+        cfg.rustflags = self.common_cfg.rustflags.clone();
+        cfg.fail_on_warnings = self.common_cfg.fail_on_warnings;
+        cfg.bin = self.common_cfg.bin.clone();
+        cfg.quiet = self.size_only || self.common_cfg.json;
+        let mut wasm = project::build_dylib(cfg.clone())?;
+        if self.optimize {
+            wasm = project::optimize_wasm(&wasm)?;
+        }
+        if self.strip {
+            let removed;
+            (wasm, removed) = project::strip_wasm(&wasm)?;
+            if !self.size_only {
+                greyln!("stripped {removed} B of custom sections from wasm");
+            }
+        }
         let project_hash =
             project::hash_project(self.common_cfg.source_files_for_project_hash.clone(), cfg)?;
         Ok((wasm, project_hash))
     }
 }
 
-/// Pretty-prints a file size based on its limits.
+/// An entry in the append-only `--size-log` file.
+#[derive(Debug, Serialize, Deserialize)]
+struct SizeLogEntry {
+    timestamp: u64,
+    project_hash: String,
+    wasm_size: usize,
+    compressed_size: usize,
+}
+
+fn append_size_log(path: &Path, entry: &SizeLogEntry) -> Result<()> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{}", serde_json::to_string(entry)?)?;
+    Ok(())
+}
+
+/// Prints how the sizes changed between the last two entries of the size log.
+fn print_size_diff(path: &Path) -> Result<()> {
+    let log = fs::read_to_string(path)?;
+    let entries: Vec<SizeLogEntry> = log
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(serde_json::from_str)
+        .collect::<Result<_, _>>()?;
+    let [.., prev, last] = entries.as_slice() else {
+        greyln!("size log needs at least two entries to diff");
+        return Ok(());
+    };
+    greyln!(
+        "wasm size: {} -> {} ({})",
+        prev.wasm_size,
+        last.wasm_size,
+        format_size_delta(prev.wasm_size, last.wasm_size)
+    );
+    greyln!(
+        "compressed size: {} -> {} ({})",
+        prev.compressed_size,
+        last.compressed_size,
+        format_size_delta(prev.compressed_size, last.compressed_size)
+    );
+    Ok(())
+}
+
+fn format_size_delta(prev: usize, last: usize) -> String {
+    let delta = last as i64 - prev as i64;
+    match delta {
+        d if d > 0 => format!("+{d} B").pink(),
+        d if d < 0 => format!("{d} B").mint(),
+        _ => "unchanged".grey(),
+    }
+}
+
+/// Pretty-prints a file size against its limits, given in bytes, along with how much of
+/// the limit it uses, e.g. `16.0 KiB / 24.0 KiB limit (67% used)`.
 pub fn format_file_size(len: usize, mid: u64, max: u64) -> String {
-    let len = ByteSize::b(len as u64);
-    let mid = ByteSize::kib(mid);
-    let max = ByteSize::kib(max);
-    if len <= mid {
-        len.mint()
+    let kib = |bytes: u64| format!("{:.1} KiB", bytes as f64 / 1024.0);
+    let len = len as u64;
+    let size = if len <= mid {
+        kib(len).mint()
     } else if len <= max {
-        len.yellow()
+        kib(len).yellow()
     } else {
-        len.pink()
-    }
+        kib(len).pink()
+    };
+    let used = len as f64 * 100.0 / max.max(1) as f64;
+    format!("{size} / {} limit ({used:.0}% used)", kib(max))
 }
 
 pub struct EthCallError {
@@ -116,22 +369,90 @@ impl From<EthCallError> for ErrReport {
     }
 }
 
-pub fn contract_deployment_calldata(code: &[u8]) -> Vec<u8> {
-    let mut code_len = [0u8; 32];
-    EU256::from(code.len()).to_big_endian(&mut code_len);
-    let mut deploy: Vec<u8> = vec![];
-    deploy.push(0x7f); // PUSH32
-    deploy.extend(code_len);
-    deploy.push(0x80); // DUP1
-    deploy.push(0x60); // PUSH1
-    deploy.push(42 + 1); // prelude + version
-    deploy.push(0x60); // PUSH1
-    deploy.push(0x00);
-    deploy.push(0x39); // CODECOPY
-    deploy.push(0x60); // PUSH1
-    deploy.push(0x00);
-    deploy.push(0xf3); // RETURN
-    deploy.push(0x00); // version
-    deploy.extend(code);
-    deploy
-}
\ No newline at end of file
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Apis, Opts};
+    use clap::Parser;
+
+    #[test]
+    fn test_format_file_size() {
+        let max = MAX_CODE_SIZE_BYTES;
+        let size = format_file_size(16 * 1024, max * 2 / 3, max);
+        assert!(size.ends_with(" / 24.0 KiB limit (67% used)"));
+        assert!(size.contains("16.0 KiB"));
+        let size = format_file_size(30 * 1024, max * 2 / 3, max);
+        assert!(size.ends_with(" / 24.0 KiB limit (125% used)"));
+    }
+
+    #[tokio::test]
+    async fn test_code_size_limit_applies_to_deployed_code() -> Result<()> {
+        // A WASM far over the limit that compresses well under it.
+        let dir = tempfile::tempdir()?;
+        let wasm = dir.path().join("contract.wasm");
+        let data = "\\00".repeat(8 * 1024);
+        let wat = format!("(module (memory 1) (data (i32.const 0) \"{data}\"))");
+        fs::write(&wasm, wasmer::wat2wasm(wat.as_bytes())?)?;
+        let wasm = wasm.to_string_lossy();
+
+        for (flag, over_limit) in [("--compression=brotli", false), ("--no-compress", true)] {
+            let args = [
+                "cargo-stylus",
+                "check",
+                "--endpoint=http://localhost:8547",
+                "--size-only",
+                "--max-code-size-bytes=1024",
+                "--wasm-file",
+                &wasm,
+                flag,
+            ];
+            let Apis::Check(cfg) = Opts::try_parse_from(args)?.command else {
+                panic!("expected the check command");
+            };
+            let outcome = diagnose(&cfg).await?;
+            let rules: Vec<_> = outcome.diagnostics.iter().map(|d| d.rule).collect();
+            assert_eq!(rules.contains(&Rule::CodeSize), over_limit);
+            if let Some(contract) = outcome.contract {
+                assert!(contract.compressed_code().len() <= 1024);
+            }
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_only_no_project_hash_drops_the_section() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let wasm = dir.path().join("contract.wasm");
+        fs::write(&wasm, wasmer::wat2wasm(b"(module (func))")?)?;
+        let wasm = wasm.to_string_lossy();
+
+        let cases: [(&[&str], bool); 4] = [
+            (&[], true),
+            (&["--strip=false"], true),
+            (&["--no-project-hash"], false),
+            (&["--no-project-hash", "--strip=false"], false),
+        ];
+        for (flags, has_hash) in cases {
+            let mut args = vec![
+                "cargo-stylus",
+                "check",
+                "--endpoint=http://localhost:8547",
+                "--size-only",
+                "--wasm-file",
+                &wasm,
+            ];
+            args.extend_from_slice(flags);
+            let Apis::Check(cfg) = Opts::try_parse_from(args)?.command else {
+                panic!("expected the check command");
+            };
+            let Some(contract) = diagnose(&cfg).await?.contract else {
+                panic!("expected the check to pass");
+            };
+            let compression = cfg.common_cfg.compression;
+            let deployed = compression::decompress_wasm(contract.compressed_code(), compression)?;
+            let hash = compression::read_project_hash(&deployed)?;
+            assert_eq!(hash.is_some(), has_hash, "{flags:?}");
+        }
+        Ok(())
+    }
+}
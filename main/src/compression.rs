@@ -0,0 +1,357 @@
+// Copyright 2023-2024, Offchain Labs, Inc.
+// For licensing, see https://github.com/OffchainLabs/cargo-stylus/blob/main/licenses/COPYRIGHT.md
+
+//! Compressing a contract for deployment, and the project hash section it carries.
+
+use brotli2::{
+    read::{BrotliDecoder, BrotliEncoder},
+    CompressMode, CompressParams,
+};
+use eyre::{bail, eyre, Result, WrapErr};
+use std::{fmt, fs, io::Read, ops::Range, path::PathBuf};
+use wasm_encoder::{CustomSection, Module, RawSection};
+use wasmparser::{Parser, Payload};
+
+/// Maximum brotli compression level used for Stylus contracts.
+pub const BROTLI_COMPRESSION_LEVEL: u32 = 11;
+
+/// Brotli window size (lgwin) used for Stylus contracts. It is pinned along with the
+/// level, rather than left to the library default, so the compressed bytes that
+/// reproducible verification compares don't depend on the brotli version.
+pub const BROTLI_WINDOW_BITS: u32 = 22;
+
+/// Zstd compression level used for Stylus contracts when opting out of brotli.
+pub const ZSTD_COMPRESSION_LEVEL: i32 = 19;
+
+/// Name of the custom wasm section that is added to contracts deployed with cargo stylus
+/// to include a hash of the Rust project's source files for reproducible verification of builds.
+pub const PROJECT_HASH_SECTION_NAME: &str = "project_hash";
+
+/// Compression scheme applied to the WASM before it is deployed.
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    #[default]
+    Brotli,
+    Zstd,
+    /// Deploy the WASM uncompressed, to rule compression out when debugging. Only nodes
+    /// that accept uncompressed programs take it.
+    None,
+}
+
+impl Compression {
+    /// The version byte written to the deployment prelude to signal this scheme.
+    pub fn prelude_version(self) -> u8 {
+        match self {
+            Self::Brotli => 0x00,
+            Self::Zstd => 0x01,
+            Self::None => 0x02,
+        }
+    }
+
+    pub fn from_prelude_version(version: u8) -> Result<Self> {
+        match version {
+            0x00 => Ok(Self::Brotli),
+            0x01 => Ok(Self::Zstd),
+            0x02 => Ok(Self::None),
+            x => bail!("unknown deployment prelude version {x}"),
+        }
+    }
+}
+
+impl fmt::Display for Compression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Brotli => write!(f, "brotli"),
+            Self::Zstd => write!(f, "zstd"),
+            Self::None => write!(f, "none"),
+        }
+    }
+}
+
+/// Reads a WASM file at a specified path and returns its compressed bytes.
+/// The project hash is embedded as a custom section unless it is `None`. With `strip`,
+/// every custom section is removed before compressing, the project hash included.
+pub fn compress_wasm(
+    wasm: &PathBuf,
+    project_hash: Option<[u8; 32]>,
+    compression: Compression,
+    strip: bool,
+) -> Result<(Vec<u8>, Vec<u8>)> {
+    let wasm =
+        fs::read(wasm).wrap_err_with(|| eyre!("failed to read Wasm {}", wasm.to_string_lossy()))?;
+    compress_wasm_bytes(&wasm, project_hash, compression, strip)
+}
+
+/// Like [`compress_wasm`], but for a WASM already in memory, e.g. one generated
+/// programmatically. Returns the processed WASM and its compressed bytes.
+pub fn compress_wasm_bytes(
+    wasm: &[u8],
+    project_hash: Option<[u8; 32]>,
+    compression: Compression,
+    strip: bool,
+) -> Result<(Vec<u8>, Vec<u8>)> {
+    // We convert the WASM from binary to text and back to binary as this trick removes any dangling
+    // mentions of reference types in the wasm body, which are not yet supported by Arbitrum chain backends.
+    let wat_str =
+        wasmprinter::print_bytes(wasm).map_err(|e| eyre!("failed to convert Wasm to Wat: {e}"))?;
+    let wasm = wasmer::wat2wasm(wat_str.as_bytes())
+        .map_err(|e| eyre!("failed to convert Wat to Wasm: {e}"))?;
+
+    // We include the project's hash as a custom section
+    // in the user's WASM so it can be verified by Cargo stylus'
+    // reproducible verification. This hash is added as a section that is
+    // ignored by WASM runtimes, so it will only exist in the file
+    // for metadata purposes.
+    let wasm = match project_hash {
+        Some(project_hash) => add_project_hash_to_wasm_file(&wasm, project_hash)
+            .wrap_err("failed to add project hash to wasm file as custom section")?,
+        None => wasm.into_owned(),
+    };
+
+    let wasm = match strip {
+        true => strip_user_metadata(&wasm, true)
+            .wrap_err("failed to strip user metadata from wasm file")?,
+        false => wasm,
+    };
+
+    let wasm = wasmer::wat2wasm(&wasm).wrap_err("failed to parse Wasm")?;
+    let compressed_bytes = compress_bytes(&wasm, compression)?;
+
+    Ok((wasm.to_vec(), compressed_bytes))
+}
+
+fn compress_bytes(wasm: &[u8], compression: Compression) -> Result<Vec<u8>> {
+    match compression {
+        Compression::Brotli => {
+            let mut params = CompressParams::new();
+            params
+                .quality(BROTLI_COMPRESSION_LEVEL)
+                .lgwin(BROTLI_WINDOW_BITS)
+                .mode(CompressMode::Generic);
+            let mut compressor = BrotliEncoder::from_params(wasm, &params);
+            let mut compressed_bytes = vec![];
+            compressor
+                .read_to_end(&mut compressed_bytes)
+                .wrap_err("failed to compress WASM bytes")?;
+            Ok(compressed_bytes)
+        }
+        Compression::None => Ok(wasm.to_vec()),
+        Compression::Zstd => zstd::stream::encode_all(wasm, ZSTD_COMPRESSION_LEVEL)
+            .wrap_err("failed to compress WASM bytes"),
+    }
+}
+
+/// Decompresses WASM bytes that were compressed with the given scheme.
+pub fn decompress_wasm(compressed: &[u8], compression: Compression) -> Result<Vec<u8>> {
+    match compression {
+        Compression::Brotli => {
+            let mut decompressor = BrotliDecoder::new(compressed);
+            let mut wasm = vec![];
+            decompressor
+                .read_to_end(&mut wasm)
+                .wrap_err("failed to decompress WASM bytes")?;
+            Ok(wasm)
+        }
+        Compression::Zstd => {
+            zstd::stream::decode_all(compressed).wrap_err("failed to decompress WASM bytes")
+        }
+        Compression::None => Ok(compressed.to_vec()),
+    }
+}
+
+// Adds the hash of the project's source files to the wasm as a custom section
+// if it does not already exist. This allows for reproducible builds by cargo stylus
+// for all Rust stylus contracts. See `cargo stylus verify --help` for more information.
+fn add_project_hash_to_wasm_file(
+    wasm_file_bytes: &[u8],
+    project_hash: [u8; 32],
+) -> Result<Vec<u8>> {
+    // An existing hash is left as is rather than overwritten.
+    if has_project_hash_section(wasm_file_bytes)? {
+        return Ok(wasm_file_bytes.to_vec());
+    }
+    Ok(add_custom_section(wasm_file_bytes, project_hash))
+}
+
+fn has_project_hash_section(wasm_file_bytes: &[u8]) -> Result<bool> {
+    let parser = wasmparser::Parser::new(0);
+    for payload in parser.parse_all(wasm_file_bytes) {
+        if let wasmparser::Payload::CustomSection(reader) = payload? {
+            if reader.name() == PROJECT_HASH_SECTION_NAME {
+                return Ok(true);
+            }
+        }
+    }
+    Ok(false)
+}
+
+/// Reads the project hash embedded in a WASM's custom section, if any.
+pub fn read_project_hash(wasm: &[u8]) -> Result<Option<[u8; 32]>> {
+    let parser = wasmparser::Parser::new(0);
+    for payload in parser.parse_all(wasm) {
+        if let wasmparser::Payload::CustomSection(reader) = payload? {
+            if reader.name() == PROJECT_HASH_SECTION_NAME {
+                let data = reader.data();
+                let hash = data.try_into().map_err(|_| {
+                    eyre!("project hash section has {} bytes, expected 32", data.len())
+                })?;
+                return Ok(Some(hash));
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// Embeds a project hash as the custom section that reproducible verification reads,
+/// for WASMs built outside of cargo. Fails if the WASM already has a different hash.
+pub fn embed_project_hash(wasm: &[u8], hash: [u8; 32]) -> Result<Vec<u8>> {
+    match read_project_hash(wasm)? {
+        Some(existing) if existing == hash => Ok(wasm.to_vec()),
+        Some(existing) => bail!("wasm already has project hash {}", hex::encode(existing)),
+        None => Ok(add_custom_section(wasm, hash)),
+    }
+}
+
+fn add_custom_section(wasm_file_bytes: &[u8], project_hash: [u8; 32]) -> Vec<u8> {
+    let mut bytes = vec![];
+    bytes.extend_from_slice(wasm_file_bytes);
+    wasm_gen::write_custom_section(&mut bytes, PROJECT_HASH_SECTION_NAME, &project_hash);
+    bytes
+}
+
+/// Removes the custom and unknown sections of a WASM, except for the project hash when
+/// `keep_project_hash` is set.
+pub fn strip_user_metadata(wasm_file_bytes: &[u8], keep_project_hash: bool) -> Result<Vec<u8>> {
+    let mut module = Module::new();
+    // Parse the input WASM and iterate over the sections
+    let parser = Parser::new(0);
+    for payload in parser.parse_all(wasm_file_bytes) {
+        match payload? {
+            Payload::CustomSection(reader)
+                if keep_project_hash && reader.name() == PROJECT_HASH_SECTION_NAME =>
+            {
+                // Keep the project hash so the contract can still be verified
+                module.section(&CustomSection {
+                    name: reader.name().into(),
+                    data: reader.data().into(),
+                });
+            }
+            Payload::CustomSection { .. } => {
+                // Skip custom sections to remove sensitive metadata
+            }
+            Payload::UnknownSection { .. } => {
+                // Skip unknown sections that might not be sensitive
+            }
+            item => {
+                // Handle other sections as normal.
+                if let Some(section) = item.as_section() {
+                    let (id, range): (u8, Range<usize>) = section;
+                    let data_slice = &wasm_file_bytes[range.start..range.end]; // Start at the beginning of the range
+                    let raw_section = RawSection {
+                        id,
+                        data: data_slice,
+                    };
+                    module.section(&raw_section);
+                }
+            }
+        }
+    }
+    // Return the stripped WASM binary
+    Ok(module.finish())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_read_project_hash() -> Result<()> {
+        let wasm = b"\0asm\x01\0\0\0";
+        assert_eq!(read_project_hash(wasm)?, None);
+
+        let project_hash = [0xab; 32];
+        let wasm = add_custom_section(wasm, project_hash);
+        assert_eq!(read_project_hash(&wasm)?, Some(project_hash));
+        Ok(())
+    }
+
+    #[test]
+    fn test_embed_project_hash() -> Result<()> {
+        let wasm = b"\0asm\x01\0\0\0";
+        let project_hash = [0xab; 32];
+        let embedded = embed_project_hash(wasm, project_hash)?;
+        assert_eq!(read_project_hash(&embedded)?, Some(project_hash));
+        assert_eq!(embed_project_hash(&embedded, project_hash)?, embedded);
+        assert!(embed_project_hash(&embedded, [0xcd; 32]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_strip_keeps_project_hash() -> Result<()> {
+        let project_hash = [0xab; 32];
+        let mut wasm = add_custom_section(b"\0asm\x01\0\0\0", project_hash);
+        wasm_gen::write_custom_section(&mut wasm, "name", b"debug names");
+        let stripped = strip_user_metadata(&wasm, true)?;
+        assert!(stripped.len() < wasm.len());
+        assert_eq!(read_project_hash(&stripped)?, Some(project_hash));
+        assert_eq!(
+            stripped,
+            add_custom_section(b"\0asm\x01\0\0\0", project_hash)
+        );
+        assert_eq!(strip_user_metadata(&wasm, false)?, b"\0asm\x01\0\0\0");
+        Ok(())
+    }
+
+    #[test]
+    fn test_compress_wasm_bytes() -> Result<()> {
+        let wasm = wasmer::wat2wasm(b"(module (func))")?;
+        let project_hash = [0xab; 32];
+        for compression in [Compression::Brotli, Compression::Zstd] {
+            let (processed, compressed) =
+                compress_wasm_bytes(&wasm, Some(project_hash), compression, false)?;
+            assert_eq!(read_project_hash(&processed)?, Some(project_hash));
+            assert_eq!(decompress_wasm(&compressed, compression)?, processed);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_strip_at_compression_keeps_project_hash() -> Result<()> {
+        let wasm = wasmer::wat2wasm(b"(module (func))")?;
+        let project_hash = [0xab; 32];
+        for compression in [Compression::Brotli, Compression::Zstd, Compression::None] {
+            let (stripped, compressed) =
+                compress_wasm_bytes(&wasm, Some(project_hash), compression, true)?;
+            assert_eq!(read_project_hash(&stripped)?, Some(project_hash));
+            let deployed = decompress_wasm(&compressed, compression)?;
+            assert_eq!(read_project_hash(&deployed)?, Some(project_hash));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_compression_is_deterministic() -> Result<()> {
+        let wasm = b"\0asm\x01\0\0\0 the same input compresses the same way".repeat(64);
+        for compression in [Compression::Brotli, Compression::Zstd] {
+            assert_eq!(
+                compress_bytes(&wasm, compression)?,
+                compress_bytes(&wasm, compression)?
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_compression_round_trip() -> Result<()> {
+        let wasm = b"\0asm\x01\0\0\0 some wasm-like bytes to compress".repeat(16);
+        for compression in [Compression::Brotli, Compression::Zstd, Compression::None] {
+            let compressed = compress_bytes(&wasm, compression)?;
+            assert_eq!(decompress_wasm(&compressed, compression)?, wasm);
+            let version = compression.prelude_version();
+            assert_eq!(Compression::from_prelude_version(version)?, compression);
+        }
+        assert!(Compression::from_prelude_version(0xff).is_err());
+        Ok(())
+    }
+}
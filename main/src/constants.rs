@@ -1,8 +1,23 @@
 // Copyright 2023-2024, Offchain Labs, Inc.
 // For licensing, see https://github.com/OffchainLabs/cargo-stylus/blob/main/licenses/COPYRIGHT.md
 
-/// Maximum brotli compression level used for Stylus contracts.
-pub const BROTLI_COMPRESSION_LEVEL: u32 = 11;
+/// Default limit on the compressed size of a contract, in bytes.
+pub const MAX_CODE_SIZE_BYTES: u64 = 24 * 1024;
+
+/// Default limit on the number of functions a contract may define, matching the
+/// limit enforced when Stylus programs are parsed onchain.
+pub const MAX_WASM_FUNCTIONS: u32 = 10_000;
+
+/// The widely deployed CREATE2 deployer proxy, which deploys the init code following a
+/// 32-byte salt in its calldata to the same address on every chain it exists on.
+pub const CREATE2_DEPLOYER_ADDRESS: &str = "0x4e59b44847b379578588920cA78FbF26c0B4956C";
+
+/// The widely deployed CREATE3 factory, whose deployments depend only on the deployer and
+/// salt, not on the init code.
+pub const CREATE3_FACTORY_ADDRESS: &str = "0x9fBB3DF7C40Da2e5A0dE984fFE2CCB7C47cd0ABf";
+
+/// The Safe `CreateCall` library (v1.3.0), which Safes delegatecall to deploy contracts.
+pub const SAFE_CREATE_CALL_ADDRESS: &str = "0x7cbB62EaA69F79e6873cD1ecB2392971036cFAa4";
 
 /// Target for compiled WASM folder in a Rust project
 pub const RUST_TARGET: &str = "wasm32-unknown-unknown";
@@ -10,9 +25,17 @@ pub const RUST_TARGET: &str = "wasm32-unknown-unknown";
 /// The default repo to clone when creating new projects
 pub const GITHUB_TEMPLATE_REPO: &str = "https://github.com/PharosNetwork/stylus-hello-world";
 
-/// Name of the custom wasm section that is added to contracts deployed with cargo stylus
-/// to include a hash of the Rust project's source files for reproducible verification of builds.
-pub const PROJECT_HASH_SECTION_NAME: &str = "project_hash";
+/// How long the git commands that fetch a project template may take, in seconds.
+pub const GIT_TIMEOUT_SECS: u64 = 120;
+
+/// Built-in project templates by name, and the repos they are cloned from.
+pub const TEMPLATES: &[(&str, &str)] = &[("hello-world", GITHUB_TEMPLATE_REPO)];
 
 /// Name of the toolchain file used to specify the Rust toolchain version for a project.
 pub const TOOLCHAIN_FILE_NAME: &str = "rust-toolchain.toml";
+
+/// Name of the optional file in the project root setting defaults for flags.
+pub const STYLUS_TOML_FILE_NAME: &str = "Stylus.toml";
+
+/// Directory in the project where each chain's deployments are recorded.
+pub const DEPLOYMENTS_DIR: &str = "deployments";
@@ -0,0 +1,193 @@
+// Copyright 2023-2024, Offchain Labs, Inc.
+// For licensing, see https://github.com/OffchainLabs/cargo-stylus/blob/main/licenses/COPYRIGHT.md
+
+use crate::{
+    util::{color::DebugColor, text},
+    DeploymentsArgs,
+};
+use ethers::types::{H160, H256};
+use eyre::{Result, WrapErr};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// A deployment as recorded in `deployments/<chain id>.json`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeploymentRecord {
+    pub chain_id: u64,
+    /// The crate name, or the file name of a prebuilt WASM.
+    pub contract: String,
+    pub endpoint: String,
+    pub address: H160,
+    pub tx_hash: H256,
+    /// Keccak hash of the deployed code.
+    pub codehash: H256,
+    /// Hex-encoded hash of the project's source files.
+    pub project_hash: String,
+    /// Seconds since the Unix epoch.
+    pub timestamp: u64,
+    /// Channel from the project's `rust-toolchain.toml`, absent for a prebuilt WASM.
+    pub toolchain: Option<String>,
+    /// Version of cargo stylus that made the deployment.
+    pub cargo_stylus_version: String,
+    pub git_commit: Option<String>,
+}
+
+/// Appends a deployment to the chain's file in the given directory, creating both as
+/// needed. Each file holds a JSON array of the deployments on that chain, oldest first.
+pub fn append_record(dir: &Path, record: &DeploymentRecord) -> Result<PathBuf> {
+    let path = dir.join(format!("{}.json", record.chain_id));
+    let mut records: Vec<DeploymentRecord> = match fs::read_to_string(&path) {
+        Ok(json) => serde_json::from_str(&json)
+            .wrap_err_with(|| format!("failed to parse {}", path.display()))?,
+        Err(_) => vec![],
+    };
+    records.push(record.clone());
+    fs::create_dir_all(dir).wrap_err("failed to create deployments dir")?;
+    fs::write(&path, serde_json::to_string_pretty(&records)?)
+        .wrap_err_with(|| format!("failed to write {}", path.display()))?;
+    Ok(path)
+}
+
+/// Reads the deployments recorded in the directory, on the given chain or on all of them.
+pub fn read_records(dir: &Path, chain_id: Option<u64>) -> Result<Vec<DeploymentRecord>> {
+    let mut paths = match chain_id {
+        Some(chain_id) => vec![dir.join(format!("{chain_id}.json"))],
+        None => {
+            let entries =
+                fs::read_dir(dir).wrap_err_with(|| format!("failed to read {}", dir.display()))?;
+            let mut paths = vec![];
+            for entry in entries {
+                let path = entry?.path();
+                if path.extension().is_some_and(|ext| ext == "json") {
+                    paths.push(path);
+                }
+            }
+            paths
+        }
+    };
+    paths.sort();
+    let mut records = vec![];
+    for path in paths {
+        if !path.exists() {
+            continue;
+        }
+        let json = fs::read_to_string(&path)?;
+        let chain: Vec<DeploymentRecord> = serde_json::from_str(&json)
+            .wrap_err_with(|| format!("failed to parse {}", path.display()))?;
+        records.extend(chain);
+    }
+    Ok(records)
+}
+
+/// Keeps the deployments of the given contract, and when `latest` is set, only the most
+/// recent deployment of each contract on each chain.
+fn filter_records(
+    records: Vec<DeploymentRecord>,
+    contract: Option<&str>,
+    latest: bool,
+) -> Vec<DeploymentRecord> {
+    let mut records: Vec<_> = records
+        .into_iter()
+        .filter(|record| contract.is_none_or(|name| record.contract == name))
+        .collect();
+    if latest {
+        let mut seen = HashSet::new();
+        records.reverse();
+        records.retain(|record| seen.insert((record.chain_id, record.contract.clone())));
+        records.reverse();
+    }
+    records
+}
+
+/// Lists the recorded deployments, as a table per deployment or as a JSON array.
+pub fn list_deployments(args: &DeploymentsArgs) -> Result<()> {
+    let records = read_records(&args.dir, args.chain_id)?;
+    let records = filter_records(records, args.contract.as_deref(), args.latest);
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&records)?);
+        return Ok(());
+    }
+    for (i, record) in records.iter().enumerate() {
+        if i > 0 {
+            println!();
+        }
+        let rows = [
+            ("contract", record.contract.clone()),
+            ("chain id", record.chain_id.to_string()),
+            ("address", record.address.debug_lavender()),
+            ("tx hash", format!("{:?}", record.tx_hash)),
+            ("codehash", format!("{:?}", record.codehash)),
+            ("timestamp", record.timestamp.to_string()),
+        ];
+        print!("{}", text::format_table(&rows));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_append_record() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let dir = dir.path().join("deployments");
+        let mut record = sample(688688, "counter", 1);
+        let path = append_record(&dir, &record)?;
+        assert_eq!(path, dir.join("688688.json"));
+        record.address = H160::repeat_byte(4);
+        append_record(&dir, &record)?;
+
+        let records: Vec<DeploymentRecord> = serde_json::from_str(&fs::read_to_string(path)?)?;
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].address, H160::repeat_byte(1));
+        assert_eq!(records[1], record);
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_and_filter_records() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let dir = dir.path();
+        for record in [
+            sample(1, "counter", 1),
+            sample(1, "token", 2),
+            sample(1, "counter", 3),
+            sample(2, "counter", 4),
+        ] {
+            append_record(dir, &record)?;
+        }
+        assert_eq!(read_records(dir, None)?.len(), 4);
+        assert_eq!(read_records(dir, Some(2))?.len(), 1);
+        assert!(read_records(dir, Some(3))?.is_empty());
+
+        let records = read_records(dir, None)?;
+        let counters = filter_records(records.clone(), Some("counter"), false);
+        assert_eq!(counters.len(), 3);
+        let latest = filter_records(records, None, true);
+        let addresses: Vec<_> = latest.iter().map(|record| record.address).collect();
+        let expected = [2, 3, 4].map(H160::repeat_byte);
+        assert_eq!(addresses, expected);
+        Ok(())
+    }
+
+    fn sample(chain_id: u64, contract: &str, address: u8) -> DeploymentRecord {
+        DeploymentRecord {
+            chain_id,
+            contract: contract.into(),
+            endpoint: "http://localhost:8547".into(),
+            address: H160::repeat_byte(address),
+            tx_hash: H256::repeat_byte(2),
+            codehash: H256::repeat_byte(3),
+            project_hash: "00".repeat(32),
+            timestamp: 1_700_000_000,
+            toolchain: Some("1.80.0".into()),
+            cargo_stylus_version: "0.5.3".into(),
+            git_commit: None,
+        }
+    }
+}
@@ -3,117 +3,726 @@
 
 #![allow(clippy::println_empty_string)]
 use crate::{
-    check, export_abi,
+    check::{self, ContractCheck},
+    compression::Compression,
+    constants::{DEPLOYMENTS_DIR, TOOLCHAIN_FILE_NAME},
+    error::StylusError,
+    export_abi,
     macros::*,
+    prelude::deployment_calldata,
+    project::{extract_cargo_project_name, extract_toolchain_channel},
+    report::{CallReport, DeployReport},
     util::{
         color::{Color, DebugColor},
-        sys,
+        sys, text,
+        timer::StepTimer,
     },
-    DeployConfig,
+    wallet::StylusSigner,
+    CommonConfig, DeployConfig, NativeTokenOpts,
 };
+use alloy_dyn_abi::{DynSolValue, Specifier};
+use alloy_ethers_typecast::{alloy_u256_to_ethers, ethers_u256_to_alloy};
+use alloy_json_abi::Constructor;
+use alloy_primitives::U256 as AU256;
+use bytesize::ByteSize;
+use deployments::DeploymentRecord;
 use ethers::core::utils::format_units;
 use ethers::{
-    core::k256::ecdsa::SigningKey,
     middleware::SignerMiddleware,
     prelude::*,
-    providers::{Middleware, Provider},
+    providers::{Middleware, MiddlewareError, Provider, StreamExt, Ws},
     signers::Signer,
-    types::{transaction::eip2718::TypedTransaction, Eip1559TransactionRequest, H160, U256, U64},
+    types::{
+        transaction::{eip2718::TypedTransaction, eip2930::AccessListItem},
+        Eip1559TransactionRequest, Transaction, H160, U256, U64,
+    },
 };
 use eyre::{bail, eyre, Result, WrapErr};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
-pub type SignerClient = SignerMiddleware<Provider<Http>, Wallet<SigningKey>>;
+pub mod deployments;
+mod safe;
+mod simulate;
 
-/// Deploys a stylus contract, activating if needed.
-pub async fn deploy(cfg: DeployConfig) -> Result<()> {
-    let contract = check::check(&cfg.check_config)
-        .await
-        .expect("cargo stylus check failed");
+/// How deployment sizes and estimates are reported.
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum ReportFormat {
+    /// Individual lines, printed when verbose or estimating gas.
+    #[default]
+    Lines,
+    /// A single aligned summary table.
+    Table,
+}
+
+pub type SignerClient = SignerMiddleware<Provider<sys::RpcClient>, StylusSigner>;
+
+/// Which block state the sender's nonce is read from, or gas is estimated against.
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum BlockTag {
+    Latest,
+    /// Includes queued transactions, matching the state a submission will land on.
+    #[default]
+    Pending,
+}
+
+impl From<BlockTag> for BlockId {
+    fn from(value: BlockTag) -> Self {
+        match value {
+            BlockTag::Latest => BlockNumber::Latest.into(),
+            BlockTag::Pending => BlockNumber::Pending.into(),
+        }
+    }
+}
+
+/// Deploys a stylus contract. Pharos activates it within the deployment tx, so unlike
+/// Arbitrum there is no separate activation tx or data fee to send.
+pub async fn deploy(cfg: DeployConfig) -> Result<DeployReport> {
+    if let Some(tx_hash) = cfg.resume_tx {
+        let provider = cfg.check_config.common_cfg.new_provider().await?;
+        let watcher = cfg.new_watcher().await?;
+        return cfg
+            .resume_deployment(tx_hash, &provider, watcher.as_ref())
+            .await;
+    }
+
+    let git_commit = check_git_state(cfg.require_clean_git)?;
+
+    // Load the wallet before building so a malformed key fails fast.
+    let wallet = cfg.auth.signer().await.wrap_err("failed to load wallet")?;
+    let contract = check::check(&cfg.check_config).await?;
     let verbose = cfg.check_config.common_cfg.verbose;
 
     // Pharos call `deploy` function on the contract, do not call `constructor` through the system contract.
-    let _constructor = export_abi::get_constructor_signature()?;
+    // The constructor arguments are instead appended to the deployment calldata.
+    let constructor = export_abi::get_constructor_signature()?;
+    let constructor_args =
+        encode_constructor_args(constructor.as_ref(), &cfg.experimental_constructor_args)?;
+    if cfg.offline {
+        let mut report = cfg
+            .sign_offline(&contract, &constructor_args, wallet)
+            .await?;
+        report.git_commit = git_commit;
+        return Ok(report);
+    }
 
-    let client = sys::new_provider(&cfg.check_config.common_cfg.endpoint)?;
-    let chain_id = client.get_chainid().await.expect("failed to get chain id");
+    let mut timer = StepTimer::start(verbose);
+    let client = cfg.check_config.common_cfg.new_provider().await?;
+    let chain_id = client
+        .get_chainid()
+        .await
+        .map_err(|e| StylusError::Rpc(format!("failed to get chain id: {e}")))?;
+    timer.lap("rpc connect");
+    if let Some(expected) = cfg.check_config.common_cfg.expected_chain_id {
+        check_chain_id(expected, chain_id.as_u64())?;
+    }
 
-    let wallet = cfg.auth.wallet().wrap_err("failed to load wallet")?;
     let wallet = wallet.with_chain_id(chain_id.as_u64());
     let sender = wallet.address();
     let client = SignerMiddleware::new(client, wallet);
+    let watcher = cfg.new_watcher().await?;
 
     if verbose {
         greyln!("sender address: {}", sender.debug_lavender());
     }
 
-    let data_fee = contract.suggest_fee()
-        + alloy_ethers_typecast::ethers_u256_to_alloy(cfg.experimental_constructor_value);
+    if let (Some(safe), Some(service)) = (cfg.safe, &cfg.safe_tx_service) {
+        let init_code = cfg.init_code(&contract, &constructor_args)?;
+        let create_call = cfg.safe_create_call;
+        let proxy = cfg.check_config.common_cfg.proxy.as_deref();
+        let safe_tx_hash =
+            safe::propose_deployment(service, safe, create_call, init_code, &client, proxy).await?;
+        greyln!("proposed deployment to Safe {}", safe.debug_lavender());
+        greyln!("safeTxHash: {}", safe_tx_hash.debug_lavender());
+        return Ok(DeployReport {
+            check: Some(contract.report().clone()),
+            contract_address: None,
+            tx_hash: None,
+            gas_estimate: U256::zero(),
+            gas_used: None,
+            effective_gas_price: None,
+            cost_wei: None,
+            git_commit,
+            calls: vec![],
+        });
+    }
 
     // Check balance early
     let balance = client
         .get_balance(sender, None)
         .await
-        .expect("failed to get balance");
-    let balance = alloy_ethers_typecast::ethers_u256_to_alloy(balance);
+        .map_err(|e| StylusError::Rpc(format!("failed to get balance: {e}")))?;
+    if !cfg.estimate_only() {
+        let data_fee = contract.suggest_fee();
+        check_funds(
+            sender,
+            balance,
+            data_fee,
+            cfg.experimental_constructor_value,
+        )?;
+    }
+
+    let mut report = cfg
+        .deploy_contract(
+            &contract,
+            &constructor_args,
+            sender,
+            &client,
+            watcher.as_ref(),
+        )
+        .await?;
+    report.git_commit = git_commit;
+    let address = report
+        .contract_address
+        .ok_or_else(|| eyre!("missing address"))?;
 
-    if balance < data_fee && !cfg.estimate_gas {
+    if cfg.estimate_total {
+        let data_fee = alloy_u256_to_ethers(contract.suggest_fee());
+        let value = cfg.experimental_constructor_value;
+        let common_cfg = &cfg.check_config.common_cfg;
+        print_total_estimate(data_fee, value, report.gas_estimate, &client, common_cfg).await?;
+    }
+    if cfg.estimate_only() {
+        return Ok(report);
+    }
+    // Record the deployment before any follow-up call can fail, as the contract is live.
+    if !cfg.no_deployments_file {
+        let record = cfg.deployment_record(&contract, chain_id.as_u64(), &report)?;
+        let path = deployments::append_record(Path::new(DEPLOYMENTS_DIR), &record)?;
+        greyln!("recorded deployment in {}", path.display().lavender());
+    }
+
+    for (name, calldata) in cfg.follow_up_calls() {
+        let result = cfg
+            .send_post_deploy_call(&name, address, calldata, sender, &client, watcher.as_ref())
+            .await;
+        let call = result.wrap_err_with(|| {
+            let succeeded = describe_calls(&report.calls);
+            eyre!("contract deployed at {address:?} but {name} failed, {succeeded}")
+        })?;
+        report.calls.push(call);
+    }
+    if let Some(selector) = cfg.smoke_test_selector {
+        smoke_test(address, selector, &client).await?;
+    }
+    if cfg.watch {
+        watch_logs(address, &client, watcher.as_ref()).await?;
+    }
+    Ok(report)
+}
+
+/// ABI-encodes the constructor arguments against the contract's constructor signature.
+fn encode_constructor_args(constructor: Option<&Constructor>, args: &[String]) -> Result<Vec<u8>> {
+    let Some(constructor) = constructor else {
+        if !args.is_empty() {
+            bail!("constructor arguments given but the contract has no constructor");
+        }
+        return Ok(vec![]);
+    };
+    let params = &constructor.inputs;
+    if params.len() != args.len() {
         bail!(
-            "not enough funds in account {} to pay for data fee\n\
-             balance {} < {}\n\
-             please see the Quickstart guide for funding new accounts:\n{}",
-            sender.red(),
-            balance.red(),
-            format!("{data_fee} wei").red(),
-            "https://docs.pharosnetwork.xyz/developer-guides".yellow(),
+            "constructor takes {} arguments but {} were given",
+            params.len(),
+            args.len()
         );
     }
+    let mut values = Vec::with_capacity(args.len());
+    for (param, arg) in params.iter().zip(args) {
+        let ty = param
+            .resolve()
+            .wrap_err_with(|| eyre!("unsupported constructor param type {}", param.ty))?;
+        let value = ty
+            .coerce_str(arg)
+            .wrap_err_with(|| eyre!("invalid constructor argument {arg} for type {ty}"))?;
+        values.push(value);
+    }
+    Ok(DynSolValue::Tuple(values).abi_encode_params())
+}
 
-    cfg.deploy_contract(contract.code(), sender, &client)
-        .await?;
+/// Lists the calls that went through before one failed.
+fn describe_calls(calls: &[CallReport]) -> String {
+    if calls.is_empty() {
+        return "no earlier calls succeeded".to_string();
+    }
+    let calls: Vec<_> = calls
+        .iter()
+        .map(|c| format!("{} in tx {:?}", c.name, c.tx_hash))
+        .collect();
+    format!("after {} succeeded", calls.join(", "))
+}
 
-    if cfg.estimate_gas {
-        return Ok(());
+/// Returns the current git commit, if any, bailing on a dirty working tree when required
+/// so the deployed project hash corresponds to committed sources.
+fn check_git_state(require_clean: bool) -> Result<Option<String>> {
+    let git = |args: &[&str]| {
+        std::process::Command::new("git")
+            .args(args)
+            .output()
+            .ok()
+            .filter(|output| output.status.success())
+            .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+    };
+    if git(&["rev-parse", "--is-inside-work-tree"]).as_deref() != Some("true") {
+        if require_clean {
+            bail!("--require-clean-git was set but the project is not in a git repository");
+        }
+        return Ok(None);
     }
+    if require_clean {
+        let Some(status) = git(&["status", "--porcelain"]) else {
+            bail!("failed to run git status");
+        };
+        let dirty = dirty_paths(&status);
+        if !dirty.is_empty() {
+            bail!(
+                "refusing to deploy with uncommitted changes to {}",
+                dirty.join(", ").red()
+            );
+        }
+    }
+    Ok(git(&["rev-parse", "HEAD"]))
+}
 
+/// The paths listed in the output of `git status --porcelain`.
+fn dirty_paths(status: &str) -> Vec<&str> {
+    status
+        .lines()
+        .filter_map(|line| line.get(3..))
+        .filter(|path| !path.is_empty())
+        .collect()
+}
+
+/// Bails if the endpoint serves a different chain than the one expected.
+fn check_chain_id(expected: u64, reported: u64) -> Result<()> {
+    if expected != reported {
+        bail!("expected chain {expected} but the endpoint reports chain {reported}");
+    }
+    Ok(())
+}
+
+fn check_codehash(expected: H256, code: &[u8]) -> Result<()> {
+    let codehash = H256(ethers::utils::keccak256(code));
+    if codehash != expected {
+        bail!("expected codehash {expected:?} but the code to deploy hashes to {codehash:?}");
+    }
+    Ok(())
+}
+
+/// Errors if the balance can't cover the data fee plus the value sent to the constructor.
+/// The comparison is done in alloy units, converting the ethers amounts here and only here.
+fn check_funds(
+    account: H160,
+    balance: U256,
+    data_fee: AU256,
+    value: U256,
+) -> Result<(), StylusError> {
+    let have = ethers_u256_to_alloy(balance);
+    let need = data_fee + ethers_u256_to_alloy(value);
+    if have < need {
+        return Err(StylusError::InsufficientFunds {
+            account,
+            have,
+            need,
+        });
+    }
     Ok(())
 }
 
 impl DeployConfig {
+    /// Whether to stop after estimating instead of sending transactions.
+    fn estimate_only(&self) -> bool {
+        self.estimate_gas || self.estimate_total || self.simulate_only
+    }
+
+    /// Describes a deployment for the chain's deployments file.
+    fn deployment_record(
+        &self,
+        contract: &ContractCheck,
+        chain_id: u64,
+        report: &DeployReport,
+    ) -> Result<DeploymentRecord> {
+        let (contract_name, toolchain) = match &self.check_config.wasm_file {
+            Some(wasm) => {
+                let name = wasm.file_name().unwrap_or_default().to_string_lossy();
+                (name.into_owned(), None)
+            }
+            None => {
+                let name = extract_cargo_project_name(&PathBuf::from("Cargo.toml"))?;
+                let toolchain = extract_toolchain_channel(&PathBuf::from(TOOLCHAIN_FILE_NAME))?;
+                (name.replace('"', ""), Some(toolchain))
+            }
+        };
+        Ok(DeploymentRecord {
+            chain_id,
+            contract: contract_name,
+            endpoint: self.check_config.common_cfg.endpoint[0].clone(),
+            address: report.contract_address.unwrap_or_default(),
+            tx_hash: report.tx_hash.unwrap_or_default(),
+            codehash: H256(ethers::utils::keccak256(contract.compressed_code())),
+            project_hash: contract.report().project_hash.clone(),
+            timestamp: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+            toolchain,
+            cargo_stylus_version: env!("CARGO_PKG_VERSION").to_string(),
+            git_commit: report.git_commit.clone(),
+        })
+    }
+
+    /// Wraps the compressed code in the deployment prelude and appends the constructor
+    /// arguments, after checking the code against `--expect-codehash`. Without arguments,
+    /// this is the calldata `check` prints as `DEPLOYMENT_CODE`.
+    fn init_code(&self, contract: &ContractCheck, constructor_args: &[u8]) -> Result<Vec<u8>> {
+        let code = contract.compressed_code();
+        if let Some(expected) = self.expect_codehash {
+            check_codehash(expected, code)?;
+        }
+        let compression = self.check_config.common_cfg.compression;
+        let mut init_code = deployment_calldata(code, compression, self.check_config.raw_code)?;
+        init_code.extend_from_slice(constructor_args);
+        Ok(init_code)
+    }
+
     async fn deploy_contract(
         &self,
-        code: &[u8],
+        contract: &ContractCheck,
+        constructor_args: &[u8],
         sender: H160,
         client: &SignerClient,
-    ) -> Result<H160> {
-        let init_code = contract_deployment_calldata(code);
+        watcher: Option<&Provider<Ws>>,
+    ) -> Result<DeployReport> {
+        let init_code = self.init_code(contract, constructor_args)?;
+        if self.simulate || self.simulate_only {
+            simulate::simulate_deployment(sender, &init_code)?;
+        }
 
-        let tx = Eip1559TransactionRequest::new()
-            .from(sender)
-            .data(init_code);
+        let nonce = client
+            .get_transaction_count(sender, Some(self.nonce_block.into()))
+            .await?;
+        let mut tx = Eip1559TransactionRequest::new().from(sender).nonce(nonce);
+        let mut address = ethers::utils::get_contract_address(sender, nonce);
+        match self.factory {
+            Some(factory) => {
+                let selector = self
+                    .factory_selector
+                    .unwrap_or_else(|| ethers::utils::id(FACTORY_DEPLOY_SIGNATURE));
+                let salt = self.experimental_deployer_salt.0;
+                tx = tx
+                    .to(factory)
+                    .data(factory_calldata(selector, init_code, salt));
+                address = factory_deploy_address(&tx, client).await?;
+            }
+            None => match self.salt {
+                Some(salt) if self.create3 => {
+                    let factory = self.create3_factory;
+                    ensure_has_code(factory, "CREATE3 factory", client)
+                        .await
+                        .wrap_err(
+                            "the CREATE3 factory isn't deployed automatically; \
+                             pass a factory deployed on this chain with --create3-factory",
+                        )?;
+                    address = create3_address(factory, sender, salt);
+                    greyln!("predicted contract address: {}", address.debug_lavender());
+                    tx = tx.to(factory).data(create3_calldata(salt, init_code));
+                }
+                Some(salt) => {
+                    let deployer = self.create2_deployer;
+                    ensure_has_code(deployer, "CREATE2 deployer", client).await?;
+                    address = ethers::utils::get_create2_address(deployer, salt, &init_code);
+                    greyln!("predicted contract address: {}", address.debug_lavender());
+                    tx = tx.to(deployer).data(create2_calldata(salt, init_code));
+                }
+                None => tx = tx.data(init_code),
+            },
+        }
+        if let Some(access_list) = &self.access_list {
+            tx = tx.access_list(access_list.clone());
+        } else if self.auto_access_list {
+            let typed = TypedTransaction::Eip1559(tx.clone());
+            let created = client
+                .create_access_list(&typed, None)
+                .await
+                .map_err(|e| StylusError::Rpc(format!("failed to create access list: {e}")))?;
+            tx = tx.access_list(created.access_list);
+        }
 
         let verbose = self.check_config.common_cfg.verbose;
+        let estimate_block = self.estimate_block.map(Into::into);
+        let mut timer = StepTimer::start(verbose);
         let gas = client
-            .estimate_gas(&TypedTransaction::Eip1559(tx.clone()), None)
+            .estimate_gas(&TypedTransaction::Eip1559(tx.clone()), estimate_block)
             .await?;
+        timer.lap("gas estimate");
+        if self.gas_report {
+            let data = tx.data.as_deref().unwrap_or_default();
+            let access_list = tx.access_list.0.as_slice();
+            let rows = gas_breakdown(data, tx.to.is_none(), access_list, gas);
+            print!("{}", text::format_table(&rows));
+        }
 
-        if self.check_config.common_cfg.verbose || self.estimate_gas {
-            print_gas_estimate("deployment", client, gas).await?;
+        let common_cfg = &self.check_config.common_cfg;
+        match self.report {
+            ReportFormat::Table => {
+                print_report_table(contract, gas, client, common_cfg).await?;
+            }
+            ReportFormat::Lines if verbose || self.estimate_only() => {
+                print_gas_estimate("deployment", client, gas, common_cfg).await?;
+            }
+            ReportFormat::Lines => {}
         }
-        if self.estimate_gas {
-            let nonce = client.get_transaction_count(sender, None).await?;
-            return Ok(ethers::utils::get_contract_address(sender, nonce));
+        let report = DeployReport {
+            check: Some(contract.report().clone()),
+            contract_address: Some(address),
+            tx_hash: None,
+            gas_estimate: gas,
+            gas_used: None,
+            effective_gas_price: None,
+            cost_wei: None,
+            git_commit: None,
+            calls: vec![],
+        };
+        if self.estimate_only() {
+            return Ok(report);
         }
 
-        let receipt = run_tx(
-            "deploy",
-            tx,
-            Some(gas),
-            self.check_config.common_cfg.max_fee_per_gas_gwei,
-            client,
-            self.check_config.common_cfg.verbose,
-        )
-        .await?;
-        let contract = receipt.contract_address.ok_or(eyre!("missing address"))?;
+        let result = run_tx("deploy", tx.clone(), Some(gas), common_cfg, client, watcher).await;
+        let receipt = match result {
+            Err(err) if self.auto_bump_gas && is_out_of_gas(&err) => {
+                // The reverted tx consumed its nonce, so the retry uses the next one.
+                let gas = gas * 3 / 2;
+                greyln!(
+                    "deploy tx ran out of gas, retrying with {}",
+                    format_gas(gas)
+                );
+                let tx = tx.nonce(nonce + 1);
+                run_tx("deploy", tx, Some(gas), common_cfg, client, watcher).await?
+            }
+            result => result?,
+        };
+        let receipt = self.await_activation(client, receipt).await?;
+        if self.salt.is_some() {
+            // The deployer is called rather than creating the contract, so the receipt has no
+            // contract address. Check the code landed where it was predicted instead.
+            let deployed = ensure_has_code(address, "predicted contract address", client).await;
+            deployed.wrap_err("deployment did not create the contract at its predicted address")?;
+        }
+        self.report_deployment(&receipt, report)
+    }
+
+    /// Builds and signs the deployment tx from the flags alone, and writes it out for
+    /// broadcasting later from a machine with network access.
+    async fn sign_offline(
+        &self,
+        contract: &ContractCheck,
+        constructor_args: &[u8],
+        wallet: StylusSigner,
+    ) -> Result<DeployReport> {
+        let common_cfg = &self.check_config.common_cfg;
+        let (Some(out), Some(nonce), Some(gas), Some(chain_id), Some(max_fee), Some(tip)) = (
+            &self.out,
+            self.nonce,
+            self.gas_limit,
+            self.chain_id,
+            common_cfg.max_fee_per_gas_gwei,
+            common_cfg.max_priority_fee_per_gas_gwei,
+        ) else {
+            bail!("--offline needs --out, --nonce, --gas-limit, --chain-id, a max fee and a tip");
+        };
+        if tip > max_fee {
+            bail!("max priority fee of {tip} gwei exceeds the max fee of {max_fee} gwei");
+        }
+        let init_code = self.init_code(contract, constructor_args)?;
+
+        let wallet = wallet.with_chain_id(chain_id);
+        let sender = wallet.address();
+        let tx = Eip1559TransactionRequest::new()
+            .from(sender)
+            .nonce(nonce)
+            .gas(gas)
+            .max_fee_per_gas(U256::from(gwei_to_wei(max_fee)?))
+            .max_priority_fee_per_gas(U256::from(gwei_to_wei(tip)?))
+            .chain_id(chain_id)
+            .data(init_code);
+        let tx = TypedTransaction::Eip1559(tx);
+        let signature = wallet
+            .sign_transaction(&tx)
+            .await
+            .wrap_err("failed to sign deployment tx")?;
+        let raw = tx.rlp_signed(&signature);
+        fs::write(out, format!("0x{}", hex::encode(&raw)))
+            .wrap_err("failed to write signed deployment tx")?;
+
+        let address = ethers::utils::get_contract_address(sender, nonce);
+        let tx_hash = H256(ethers::utils::keccak256(&raw));
+        greyln!("wrote signed deployment tx to {}", out.display().lavender());
+        greyln!("deployment tx hash: {}", tx_hash.debug_lavender());
+        greyln!("contract will deploy at: {}", address.debug_lavender());
+        Ok(DeployReport {
+            check: Some(contract.report().clone()),
+            contract_address: Some(address),
+            tx_hash: Some(tx_hash),
+            gas_estimate: gas.into(),
+            gas_used: None,
+            effective_gas_price: None,
+            cost_wei: None,
+            git_commit: None,
+            calls: vec![],
+        })
+    }
+
+    /// Picks up a deployment tx that was already submitted, e.g. by a run that was
+    /// interrupted, and reports on it as if it had just been sent.
+    async fn resume_deployment(
+        &self,
+        tx_hash: H256,
+        provider: &Provider<sys::RpcClient>,
+        watcher: Option<&Provider<Ws>>,
+    ) -> Result<DeployReport> {
+        greyln!("resuming deployment tx: {}", tx_hash.debug_lavender());
+        let tx = provider
+            .get_transaction(tx_hash)
+            .await
+            .map_err(|e| StylusError::Rpc(format!("failed to get tx: {e}")))?
+            .ok_or_else(|| eyre!("tx {} not found", tx_hash.debug_red()))?;
+        let receipt = match watcher {
+            Some(ws) => watch_receipt(ws, tx_hash).await,
+            None => PendingTransaction::new(tx_hash, provider).await,
+        };
+        let name = "deploy".to_string();
+        let Some(receipt) = receipt.wrap_err("tx failed to complete")? else {
+            return Err(StylusError::MissingReceipt { name, tx_hash }.into());
+        };
+        if receipt.status != Some(U64::from(1)) {
+            return Err(StylusError::Revert {
+                name,
+                tx_hash,
+                reason: None,
+            }
+            .into());
+        }
+        let receipt = self.await_activation(provider, receipt).await?;
+        let contract_address = self.resumed_address(&tx, &receipt, provider).await?;
+        let report = DeployReport {
+            check: None,
+            contract_address: Some(contract_address),
+            tx_hash: Some(tx_hash),
+            gas_estimate: tx.gas,
+            gas_used: receipt.gas_used,
+            effective_gas_price: receipt.effective_gas_price,
+            cost_wei: None,
+            git_commit: None,
+            calls: vec![],
+        };
+        self.report_deployment(&receipt, report)
+    }
+
+    /// Where a resumed deployment tx deployed to. Creation txs have it in their receipt,
+    /// while factory and CREATE2 deployments have it recomputed from the tx itself.
+    async fn resumed_address(
+        &self,
+        tx: &Transaction,
+        receipt: &TransactionReceipt,
+        provider: &Provider<sys::RpcClient>,
+    ) -> Result<H160> {
+        if let Some(address) = receipt.contract_address {
+            return Ok(address);
+        }
+        let Some(to) = tx.to else {
+            bail!("missing address");
+        };
+        if self.factory.is_some() {
+            // Replay the call on the state the tx ran against to learn what the factory returned.
+            let block = receipt
+                .block_number
+                .ok_or_else(|| eyre!("deployment tx is not in a block"))?;
+            let call = Eip1559TransactionRequest::new()
+                .from(tx.from)
+                .to(to)
+                .data(tx.input.clone());
+            let output = provider
+                .call(&TypedTransaction::Eip1559(call), Some((block - 1).into()))
+                .await
+                .map_err(|e| StylusError::Rpc(format!("failed to call factory: {e}")))?;
+            if output.len() < 32 {
+                bail!("factory deploy method did not return an address");
+            }
+            return Ok(H160::from_slice(&output[12..32]));
+        }
+        let Some(salt) = self.salt else {
+            bail!("missing address");
+        };
+        if self.create3 {
+            return Ok(create3_address(to, tx.from, salt));
+        }
+        let Some(init_code) = tx.input.get(32..) else {
+            bail!("deployment tx is too short to be a CREATE2 deployer call");
+        };
+        Ok(ethers::utils::get_create2_address(to, salt, init_code))
+    }
+
+    /// Pharos activates contracts in the deployment tx, so activation holds once that tx
+    /// does. Waits until it is `--activation-confirmations` blocks deep, re-reading the
+    /// receipt each block so a shallow reorg that drops or reverts it is caught.
+    async fn await_activation<M: Middleware>(
+        &self,
+        client: &M,
+        receipt: TransactionReceipt,
+    ) -> Result<TransactionReceipt> {
+        let confirmations = self.activation_confirmations;
+        if confirmations <= 1 {
+            return Ok(receipt);
+        }
+        let tx_hash = receipt.transaction_hash;
+        greyln!(
+            "waiting for {} confirmations of activation",
+            confirmations.lavender()
+        );
+        loop {
+            let head = client
+                .get_block_number()
+                .await
+                .map_err(|e| StylusError::Rpc(format!("failed to get block number: {e}")))?;
+            let current = client
+                .get_transaction_receipt(tx_hash)
+                .await
+                .map_err(|e| StylusError::Rpc(format!("failed to get receipt: {e}")))?;
+            let Some(current) = current else {
+                bail!(
+                    "deployment tx {} was dropped by a reorg",
+                    tx_hash.debug_red()
+                );
+            };
+            if current.status != Some(U64::from(1)) {
+                bail!(
+                    "deployment tx {} reverted after a reorg",
+                    tx_hash.debug_red()
+                );
+            }
+            let included = current.block_number.unwrap_or(head);
+            if head.saturating_sub(included).as_u64() + 1 >= confirmations {
+                return Ok(current);
+            }
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        }
+    }
+
+    /// Prints the outcome of a confirmed deployment tx and fills it into the report.
+    fn report_deployment(
+        &self,
+        receipt: &TransactionReceipt,
+        mut report: DeployReport,
+    ) -> Result<DeployReport> {
+        let verbose = self.check_config.common_cfg.verbose;
+        let native = &self.check_config.common_cfg.native_token;
+        // Factory and CREATE2 deployments aren't creation txs, so the address is the one the
+        // factory returned or that was predicted.
+        let contract = match (receipt.contract_address, report.contract_address) {
+            (Some(contract), _) => contract,
+            (None, Some(predicted)) if self.factory.is_some() || self.salt.is_some() => predicted,
+            _ => bail!("missing address"),
+        };
         let address = contract.debug_lavender();
 
         if verbose {
@@ -122,17 +731,256 @@ impl DeployConfig {
                 "deployed code at address: {address} {} {gas}",
                 "with".grey()
             );
+            print_tx_cost("deployment", receipt, native)?;
         } else {
             greyln!("deployed code at address: {address}");
         }
         let tx_hash = receipt.transaction_hash.debug_lavender();
         greyln!("deployment tx hash: {tx_hash}");
-        Ok(contract)
+
+        report.contract_address = Some(contract);
+        report.tx_hash = Some(receipt.transaction_hash);
+        report.gas_used = receipt.gas_used;
+        report.effective_gas_price = receipt.effective_gas_price;
+        report.cost_wei = receipt
+            .gas_used
+            .zip(receipt.effective_gas_price)
+            .and_then(|(gas, price)| gas.checked_mul(price));
+        Ok(report)
+    }
+
+    /// Connects to the websocket endpoint used to await receipts, if one was given or the
+    /// endpoint itself is a websocket.
+    async fn new_watcher(&self) -> Result<Option<Provider<Ws>>> {
+        let common_cfg = &self.check_config.common_cfg;
+        let ws_endpoint = common_cfg.endpoint.iter().find(|url| sys::is_ws_url(url));
+        match common_cfg.endpoint_ws.as_ref().or(ws_endpoint) {
+            Some(url) => Ok(Some(
+                sys::new_ws_provider(url, &common_cfg.rpc_options()).await?,
+            )),
+            None => Ok(None),
+        }
+    }
+
+    /// The calls to send to the contract once deployed, named and in order.
+    fn follow_up_calls(&self) -> Vec<(String, Bytes)> {
+        let mut calls = vec![];
+        if let Some(calldata) = &self.post_deploy_call {
+            calls.push(("post-deploy call".to_string(), calldata.clone()));
+        }
+        for (i, calldata) in self.then.iter().enumerate() {
+            calls.push((format!("follow-up call {}", i + 1), calldata.clone()));
+        }
+        calls
+    }
+
+    /// Sends a follow-up transaction to a freshly deployed contract, e.g. to initialize it.
+    async fn send_post_deploy_call(
+        &self,
+        name: &str,
+        address: H160,
+        calldata: Bytes,
+        sender: H160,
+        client: &SignerClient,
+        watcher: Option<&Provider<Ws>>,
+    ) -> Result<CallReport> {
+        let tx = Eip1559TransactionRequest::new()
+            .from(sender)
+            .to(address)
+            .data(calldata);
+
+        let gas = client
+            .estimate_gas(&TypedTransaction::Eip1559(tx.clone()), None)
+            .await?;
+        let common_cfg = &self.check_config.common_cfg;
+        print_gas_estimate(name, client, gas, common_cfg).await?;
+
+        let receipt = run_tx(name, tx, Some(gas), common_cfg, client, watcher).await?;
+        greyln!(
+            "{name} tx hash: {}",
+            receipt.transaction_hash.debug_lavender()
+        );
+        Ok(CallReport {
+            name: name.to_string(),
+            tx_hash: receipt.transaction_hash,
+            gas_used: receipt.gas_used,
+        })
+    }
+}
+
+/// The factory method called by default when deploying through `--factory`.
+const FACTORY_DEPLOY_SIGNATURE: &str = "deploy(bytes,bytes32)";
+
+const CREATE3_DEPLOY_SIGNATURE: &str = "deploy(bytes32,bytes)";
+
+/// Init code of the proxy the CREATE3 factory CREATE2s, which creates the contract from
+/// the init code it is called with.
+const CREATE3_PROXY_BYTECODE: [u8; 16] = [
+    0x67, 0x36, 0x3d, 0x3d, 0x37, 0x36, 0x3d, 0x34, 0xf0, 0x3d, 0x52, 0x60, 0x08, 0x60, 0x18, 0xf3,
+];
+
+/// Encodes a call to a factory's deploy method, passing it the deployment calldata and salt.
+fn factory_calldata(selector: [u8; 4], init_code: Vec<u8>, salt: [u8; 32]) -> Vec<u8> {
+    let args = ethers::abi::encode(&[
+        ethers::abi::Token::Bytes(init_code),
+        ethers::abi::Token::FixedBytes(salt.to_vec()),
+    ]);
+    [selector.as_slice(), &args].concat()
+}
+
+/// Encodes a call to the CREATE2 deployer proxy, which takes the salt followed by the
+/// init code as raw calldata.
+fn create2_calldata(salt: H256, init_code: Vec<u8>) -> Vec<u8> {
+    [salt.as_bytes(), &init_code].concat()
+}
+
+/// Encodes a call to the CREATE3 factory's `deploy(bytes32,bytes)`.
+fn create3_calldata(salt: H256, init_code: Vec<u8>) -> Vec<u8> {
+    let selector = ethers::utils::id(CREATE3_DEPLOY_SIGNATURE);
+    let args = ethers::abi::encode(&[
+        ethers::abi::Token::FixedBytes(salt.as_bytes().to_vec()),
+        ethers::abi::Token::Bytes(init_code),
+    ]);
+    [selector.as_slice(), &args].concat()
+}
+
+/// Computes where the CREATE3 factory deploys for the sender and salt. The factory mixes
+/// the sender into the salt, CREATE2s a fixed proxy with it, and has the proxy CREATE the
+/// contract as its first deployment.
+fn create3_address(factory: H160, sender: H160, salt: H256) -> H160 {
+    let salt = ethers::utils::keccak256([sender.as_bytes(), salt.as_bytes()].concat());
+    let proxy_hash = ethers::utils::keccak256(CREATE3_PROXY_BYTECODE);
+    let proxy = ethers::utils::get_create2_address_from_hash(factory, salt, proxy_hash);
+    ethers::utils::get_contract_address(proxy, 1)
+}
+
+/// Fails if there is no code at the address.
+async fn ensure_has_code(address: H160, name: &str, client: &SignerClient) -> Result<()> {
+    let code = client
+        .get_code(address, None)
+        .await
+        .map_err(|e| StylusError::Rpc(format!("failed to get code: {e}")))?;
+    if code.is_empty() {
+        bail!("no code at {name} {}", address.debug_red());
+    }
+    Ok(())
+}
+
+/// Calls the factory ahead of sending the tx to learn the address it will deploy to.
+async fn factory_deploy_address(
+    tx: &Eip1559TransactionRequest,
+    client: &SignerClient,
+) -> Result<H160> {
+    let output = client
+        .call(&TypedTransaction::Eip1559(tx.clone()), None)
+        .await
+        .map_err(|e| StylusError::Rpc(format!("failed to call factory: {e}")))?;
+    if output.len() < 32 {
+        bail!("factory deploy method did not return an address");
+    }
+    Ok(H160::from_slice(&output[12..32]))
+}
+
+/// Makes a read-only call to the deployed contract to confirm it executes.
+async fn smoke_test(address: H160, selector: [u8; 4], client: &SignerClient) -> Result<()> {
+    let tx = Eip1559TransactionRequest::new()
+        .to(address)
+        .data(selector.to_vec());
+    match client.call(&TypedTransaction::Eip1559(tx), None).await {
+        Ok(output) => {
+            greyln!(
+                "smoke test call {} returned: {}",
+                hex::encode(selector).lavender(),
+                format!("0x{}", hex::encode(&output)).lavender()
+            );
+            Ok(())
+        }
+        Err(err) => {
+            let reason = err
+                .as_error_response()
+                .and_then(|e| e.as_revert_data())
+                .map(|data| decode_revert_reason(&data))
+                .unwrap_or_else(|| err.to_string());
+            bail!(
+                "smoke test call {} to {} reverted: {}",
+                hex::encode(selector),
+                address.debug_red(),
+                reason.red()
+            );
+        }
     }
 }
 
-pub async fn print_gas_estimate(name: &str, client: &SignerClient, gas: U256) -> Result<()> {
+/// Decodes a Solidity `Error(string)` revert reason, falling back to the raw hex.
+pub fn decode_revert_reason(data: &[u8]) -> String {
+    const ERROR_SELECTOR: [u8; 4] = [0x08, 0xc3, 0x79, 0xa0];
+    if let Some(payload) = data.strip_prefix(&ERROR_SELECTOR) {
+        if let Ok(tokens) = ethers::abi::decode(&[ethers::abi::ParamType::String], payload) {
+            if let Some(reason) = tokens.into_iter().next().and_then(|t| t.into_string()) {
+                return reason;
+            }
+        }
+    }
+    format!("0x{}", hex::encode(data))
+}
+
+/// The gas price txs are expected to pay: the node's suggestion, raised by
+/// `--gas-price-bump-percent` if set. Bails if it exceeds the gas price cap.
+async fn suggested_gas_price(client: &SignerClient, cfg: &CommonConfig) -> Result<U256> {
     let gas_price = client.get_gas_price().await?;
+    let gas_price = match cfg.gas_price_bump_percent {
+        Some(percent) => bump_by_percent(gas_price, percent)?,
+        None => gas_price,
+    };
+    if let Some(cap) = cfg.gas_price_cap_gwei {
+        check_gas_price_cap(gas_price, cap)?;
+    }
+    Ok(gas_price)
+}
+
+/// Fees for when none are specified: the latest base fee with headroom for it to rise over
+/// the next blocks, plus the suggested priority fee. Returns `None` on chains without a base fee.
+async fn default_fees(client: &SignerClient, multiplier: f64) -> Result<Option<(U256, U256)>> {
+    let block = client
+        .get_block(BlockNumber::Latest)
+        .await
+        .map_err(|e| StylusError::Rpc(format!("failed to get latest block: {e}")))?;
+    let Some(base_fee) = block.and_then(|b| b.base_fee_per_gas) else {
+        return Ok(None);
+    };
+    let (_, priority_fee) = client.estimate_eip1559_fees(None).await?;
+    let max_fee = fee_with_headroom(base_fee, multiplier, priority_fee)?;
+    Ok(Some((max_fee, priority_fee)))
+}
+
+/// Computes `base_fee * multiplier + priority_fee`, with the multiplier taken to two decimals.
+fn fee_with_headroom(base_fee: U256, multiplier: f64, priority_fee: U256) -> Result<U256> {
+    if multiplier.is_nan() || multiplier < 1.0 {
+        bail!("fee multiplier must be at least 1, got {multiplier}");
+    }
+    let factor = U256::from((multiplier * 100.0).round() as u64);
+    let Some(scaled) = base_fee.checked_mul(factor) else {
+        bail!("overflow occurred while applying fee multiplier {multiplier}");
+    };
+    Ok(scaled / 100 + priority_fee)
+}
+
+fn bump_by_percent(value: U256, percent: u64) -> Result<U256> {
+    let factor = U256::from(percent) + 100;
+    let Some(bumped) = value.checked_mul(factor) else {
+        bail!("overflow occurred while bumping gas price by {percent}%");
+    };
+    Ok(bumped / 100)
+}
+
+pub async fn print_gas_estimate(
+    name: &str,
+    client: &SignerClient,
+    gas: U256,
+    cfg: &CommonConfig,
+) -> Result<()> {
+    let gas_price = suggested_gas_price(client, cfg).await?;
+    let native = &cfg.native_token;
     greyln!("estimates");
     greyln!("{} tx gas: {}", name, gas.debug_lavender());
     greyln!(
@@ -140,78 +988,325 @@ pub async fn print_gas_estimate(name: &str, client: &SignerClient, gas: U256) ->
         format_units(gas_price, "gwei")?.debug_lavender()
     );
     let total_cost = gas_price.checked_mul(gas).unwrap_or_default();
-    let eth_estimate = format_units(total_cost, "ether")?;
     greyln!(
-        "{} tx total cost: {} ETH",
+        "{} tx total cost: {}",
         name,
-        eth_estimate.debug_lavender()
+        native.format(total_cost)?.lavender()
     );
     Ok(())
 }
 
+/// Prints the data fee, constructor value and deployment gas cost as one budget figure.
+/// Pharos activates contracts as part of the deployment tx, so there is no activation gas.
+async fn print_total_estimate(
+    data_fee: U256,
+    value: U256,
+    gas: U256,
+    client: &SignerClient,
+    cfg: &CommonConfig,
+) -> Result<()> {
+    let gas_price = suggested_gas_price(client, cfg).await?;
+    let native = &cfg.native_token;
+    let gas_cost = gas_price.checked_mul(gas).unwrap_or_default();
+    let total = data_fee + value + gas_cost;
+    greyln!("total deployment cost estimate");
+    greyln!("data fee: {}", native.format(data_fee)?.lavender());
+    greyln!("constructor value: {}", native.format(value)?.lavender());
+    greyln!(
+        "deployment gas cost: {}",
+        native.format(gas_cost)?.lavender()
+    );
+    greyln!("total: {}", native.format(total)?.mint());
+    Ok(())
+}
+
+/// Prints the contract sizes and deployment estimate as a single aligned table.
+async fn print_report_table(
+    contract: &ContractCheck,
+    gas: U256,
+    client: &SignerClient,
+    cfg: &CommonConfig,
+) -> Result<()> {
+    let gas_price = suggested_gas_price(client, cfg).await?;
+    let native = &cfg.native_token;
+    let total_cost = gas_price.checked_mul(gas).unwrap_or_default();
+    let rows = [
+        (
+            "wasm size",
+            ByteSize::b(contract.code().len() as u64).to_string(),
+        ),
+        (
+            match contract.report().compression {
+                Compression::None => "uncompressed size",
+                _ => "compressed size",
+            },
+            ByteSize::b(contract.compressed_size() as u64).to_string(),
+        ),
+        ("deployment gas", gas.to_string()),
+        (
+            "gas price",
+            format!("{} gwei", format_units(gas_price, "gwei")?),
+        ),
+        ("total cost", native.format(total_cost)?),
+    ];
+    print!("{}", text::format_table(&rows));
+    Ok(())
+}
+
+impl NativeTokenOpts {
+    /// Formats a wei-denominated amount in the native token, e.g. `0.0123 PHAR`.
+    pub fn format(&self, amount: U256) -> Result<String> {
+        let amount = format_units(amount, self.native_decimals)?;
+        Ok(format!("{amount} {}", self.native_symbol))
+    }
+}
+
+/// Prints what a confirmed tx actually paid, as opposed to the pre-flight estimate.
+pub fn print_tx_cost(
+    name: &str,
+    receipt: &TransactionReceipt,
+    native: &NativeTokenOpts,
+) -> Result<()> {
+    let (Some(gas_used), Some(gas_price)) = (receipt.gas_used, receipt.effective_gas_price) else {
+        return Ok(());
+    };
+    let cost = gas_used.checked_mul(gas_price).unwrap_or_default();
+    greyln!(
+        "{} effective gas price: {} gwei",
+        name,
+        format_units(gas_price, "gwei")?.lavender()
+    );
+    greyln!("{} tx cost paid: {}", name, native.format(cost)?.lavender());
+    Ok(())
+}
+
 pub async fn run_tx(
     name: &str,
     tx: Eip1559TransactionRequest,
     gas: Option<U256>,
-    max_fee_per_gas_gwei: Option<u128>,
+    cfg: &CommonConfig,
     client: &SignerClient,
-    verbose: bool,
+    watcher: Option<&Provider<Ws>>,
 ) -> Result<TransactionReceipt> {
     let mut tx = tx;
     if let Some(gas) = gas {
         tx.gas = Some(gas);
     }
-    if let Some(max_fee) = max_fee_per_gas_gwei {
+    if let Some(max_fee) = cfg.max_fee_per_gas_gwei {
         tx.max_fee_per_gas = Some(U256::from(gwei_to_wei(max_fee)?));
+        if let Some(tip) = cfg.max_priority_fee_per_gas_gwei {
+            tx.max_priority_fee_per_gas = Some(U256::from(gwei_to_wei(tip)?));
+        }
+    } else if cfg.gas_price_bump_percent.is_some() {
+        tx.max_fee_per_gas = Some(suggested_gas_price(client, cfg).await?);
+    } else if let Some((max_fee, priority_fee)) = default_fees(client, cfg.fee_multiplier).await? {
+        tx.max_fee_per_gas = Some(max_fee);
+        tx.max_priority_fee_per_gas = Some(priority_fee);
+    }
+    if let Some(cap) = cfg.gas_price_cap_gwei {
+        let gas_price = match tx.max_fee_per_gas {
+            Some(max_fee) => max_fee,
+            None => client.get_gas_price().await?,
+        };
+        check_gas_price_cap(gas_price, cap)?;
     }
     let tx = TypedTransaction::Eip1559(tx);
-    let tx = client.send_transaction(tx, None).await?;
-    let tx_hash = tx.tx_hash();
-    if verbose {
-        greyln!("sent {name} tx: {}", tx_hash.debug_lavender());
+    let mut timer = StepTimer::start(cfg.verbose);
+    let pending = client.send_transaction(tx.clone(), None).await?;
+    if cfg.verbose {
+        greyln!("sent {name} tx: {}", pending.tx_hash().debug_lavender());
     }
-    let Some(receipt) = tx.await.wrap_err("tx failed to complete")? else {
-        bail!("failed to get receipt for tx {}", tx_hash.lavender());
+    timer.lap(&format!("{name} tx submission"));
+    let timeout = cfg.tx_timeout.map(Duration::from_secs);
+    await_receipt(name, pending, &tx, client, watcher, timeout, timer).await
+}
+
+/// Awaits the receipt of a submitted tx, failing if it went missing, reverted, or wasn't
+/// confirmed within the timeout.
+pub async fn await_receipt<M: Middleware, P: JsonRpcClient>(
+    name: &str,
+    pending: PendingTransaction<'_, P>,
+    tx: &TypedTransaction,
+    client: &M,
+    watcher: Option<&Provider<Ws>>,
+    timeout: Option<Duration>,
+    mut timer: StepTimer,
+) -> Result<TransactionReceipt> {
+    let tx_hash = pending.tx_hash();
+    let gas_limit = tx.gas().copied();
+    let receipt = async {
+        match watcher {
+            Some(ws) => watch_receipt(ws, tx_hash).await,
+            None => pending.await,
+        }
+    };
+    let receipt = async {
+        match timeout {
+            Some(timeout) => tokio::time::timeout(timeout, receipt)
+                .await
+                .map_err(|_| timeout),
+            None => Ok(receipt.await),
+        }
+    };
+    // The tx is paid for once sent, so don't let Ctrl-C lose track of it.
+    let receipt = tokio::select! {
+        receipt = receipt => receipt,
+        _ = tokio::signal::ctrl_c() => {
+            egreyln!(
+                "{name} tx {} was submitted and may still be pending",
+                tx_hash.debug_lavender()
+            );
+            std::process::exit(130);
+        }
+    };
+    timer.lap(&format!("{name} tx confirmation"));
+    let receipt = receipt.map_err(|timeout| {
+        eyre!(
+            "{name} tx {} was not confirmed within {}s and may still be pending",
+            tx_hash.debug_lavender(),
+            timeout.as_secs()
+        )
+    })?;
+    let Some(receipt) = receipt.wrap_err("tx failed to complete")? else {
+        return Err(StylusError::MissingReceipt {
+            name: name.to_string(),
+            tx_hash,
+        }
+        .into());
     };
     if receipt.status != Some(U64::from(1)) {
-        bail!("{name} tx reverted {}", tx_hash.debug_red());
+        // A revert that burned the entire gas limit ran out of gas rather than failing in logic.
+        if gas_limit.is_some() && receipt.gas_used == gas_limit {
+            return Err(StylusError::OutOfGas {
+                name: name.to_string(),
+                tx_hash,
+            }
+            .into());
+        }
+        return Err(StylusError::Revert {
+            name: name.to_string(),
+            tx_hash,
+            reason: replay_revert_reason(tx, receipt.block_number, client).await,
+        }
+        .into());
     }
     Ok(receipt)
 }
 
-/// Prepares an EVM bytecode prelude for contract creation.
-pub fn contract_deployment_calldata(code: &[u8]) -> Vec<u8> {
-    let mut code_len = [0u8; 32];
-    U256::from(code.len()).to_big_endian(&mut code_len);
-    let mut deploy: Vec<u8> = vec![];
-    deploy.push(0x7f); // PUSH32
-    deploy.extend(code_len);
-    deploy.push(0x80); // DUP1
-    deploy.push(0x60); // PUSH1
-    deploy.push(42 + 1); // prelude + version
-    deploy.push(0x60); // PUSH1
-    deploy.push(0x00);
-    deploy.push(0x39); // CODECOPY
-    deploy.push(0x60); // PUSH1
-    deploy.push(0x00);
-    deploy.push(0xf3); // RETURN
-    deploy.push(0x00); // version
-    deploy.extend(code);
-    deploy
-}
-
-pub fn extract_contract_evm_deployment_prelude(calldata: &[u8]) -> Vec<u8> {
-    // The length of the prelude, version part is 42 + 1 as per the code
-    let metadata_length = 42 + 1;
-    // Extract and return the metadata part
-    calldata[0..metadata_length].to_vec()
-}
-
-pub fn extract_compressed_wasm(calldata: &[u8]) -> Vec<u8> {
-    // The length of the prelude, version part is 42 + 1 as per the code
-    let metadata_length = 42 + 1;
-    // Extract and return the metadata part
-    calldata[metadata_length..].to_vec()
+/// Replays a reverted tx as an `eth_call` against its block to recover the revert reason.
+/// This is best effort, as the replay can't see the state the tx observed mid-block.
+async fn replay_revert_reason<M: Middleware>(
+    tx: &TypedTransaction,
+    block: Option<U64>,
+    client: &M,
+) -> Option<String> {
+    let err = client.call(tx, block.map(Into::into)).await.err()?;
+    let data = err.as_error_response()?.as_revert_data()?;
+    Some(decode_revert_reason(&data))
+}
+
+/// Awaits a receipt by checking on each new block of a websocket subscription.
+async fn watch_receipt(
+    ws: &Provider<Ws>,
+    tx_hash: H256,
+) -> Result<Option<TransactionReceipt>, ProviderError> {
+    let mut blocks = ws.subscribe_blocks().await?;
+    loop {
+        if let Some(receipt) = ws.get_transaction_receipt(tx_hash).await? {
+            return Ok(Some(receipt));
+        }
+        if blocks.next().await.is_none() {
+            return Ok(None);
+        }
+    }
+}
+
+/// Prints the logs a contract emits as they land, until interrupted. Logs are streamed
+/// over the websocket endpoint when one is configured, and polled for otherwise.
+async fn watch_logs(
+    address: H160,
+    client: &SignerClient,
+    watcher: Option<&Provider<Ws>>,
+) -> Result<()> {
+    greyln!(
+        "watching logs from {}, press Ctrl-C to stop",
+        address.debug_lavender()
+    );
+    let filter = Filter::new().address(address);
+    match watcher {
+        Some(ws) => print_logs(ws.subscribe_logs(&filter).await?).await,
+        None => print_logs(client.watch(&filter).await?).await,
+    }
+    bail!("log stream from {} ended", address.debug_red())
+}
+
+async fn print_logs(mut logs: impl StreamExt<Item = Log> + Unpin) {
+    while let Some(log) = logs.next().await {
+        let block = log.block_number.unwrap_or_default();
+        let tx = log.transaction_hash.unwrap_or_default();
+        greyln!("log in block {block}, tx {}", tx.debug_lavender());
+        for (i, topic) in log.topics.iter().enumerate() {
+            println!("  topic {i}: {topic:?}");
+        }
+        println!("  data: 0x{}", hex::encode(&log.data));
+    }
+}
+
+/// Gas every transaction pays up front.
+const TX_BASE_GAS: u64 = 21_000;
+/// Extra gas a contract creation tx pays up front.
+const TX_CREATE_GAS: u64 = 32_000;
+const TX_DATA_ZERO_GAS: u64 = 4;
+const TX_DATA_NON_ZERO_GAS: u64 = 16;
+/// Gas per 32-byte word of init code, per EIP-3860.
+const INIT_CODE_WORD_GAS: u64 = 2;
+const ACCESS_LIST_ADDRESS_GAS: u64 = 2_400;
+const ACCESS_LIST_STORAGE_KEY_GAS: u64 = 1_900;
+
+/// Attributes a deployment's estimated gas to the intrinsic costs, which follow from the
+/// tx alone, with whatever remains spent executing the deployment.
+fn gas_breakdown(
+    data: &[u8],
+    creation: bool,
+    access_list: &[AccessListItem],
+    total: U256,
+) -> Vec<(&'static str, String)> {
+    let zeros = data.iter().filter(|b| **b == 0).count() as u64;
+    let non_zeros = data.len() as u64 - zeros;
+    let mut parts = vec![("base", TX_BASE_GAS)];
+    if creation {
+        parts.push(("contract creation", TX_CREATE_GAS));
+        parts.push((
+            "init code words",
+            INIT_CODE_WORD_GAS * (data.len() as u64).div_ceil(32),
+        ));
+    }
+    parts.push(("calldata zero bytes", zeros * TX_DATA_ZERO_GAS));
+    parts.push(("calldata non-zero bytes", non_zeros * TX_DATA_NON_ZERO_GAS));
+    if !access_list.is_empty() {
+        let keys: usize = access_list.iter().map(|i| i.storage_keys.len()).sum();
+        let cost = ACCESS_LIST_ADDRESS_GAS * access_list.len() as u64
+            + ACCESS_LIST_STORAGE_KEY_GAS * keys as u64;
+        parts.push(("access list", cost));
+    }
+    let total = total.try_into().unwrap_or(u64::MAX);
+    let intrinsic: u64 = parts.iter().map(|(_, gas)| gas).sum();
+    parts.push(("execution", total.saturating_sub(intrinsic)));
+
+    let share = |gas: u64| gas as f64 * 100.0 / total.max(1) as f64;
+    let mut rows: Vec<_> = parts
+        .into_iter()
+        .map(|(name, gas)| (name, format!("{gas} gas ({:.1}%)", share(gas))))
+        .collect();
+    rows.push(("total", format!("{total} gas")));
+    rows
+}
+
+fn is_out_of_gas(err: &eyre::Report) -> bool {
+    matches!(
+        err.downcast_ref::<StylusError>(),
+        Some(StylusError::OutOfGas { .. })
+    )
 }
 
 pub fn format_gas(gas: U256) -> String {
@@ -226,6 +1321,19 @@ pub fn format_gas(gas: U256) -> String {
     }
 }
 
+/// Bails if the gas price exceeds the user's cap rather than sending an expensive tx.
+pub fn check_gas_price_cap(gas_price: U256, cap_gwei: u128) -> Result<()> {
+    let cap = U256::from(gwei_to_wei(cap_gwei)?);
+    if gas_price > cap {
+        bail!(
+            "gas price {} gwei exceeds the cap of {} gwei",
+            format_units(gas_price, "gwei")?.red(),
+            cap_gwei.mint()
+        );
+    }
+    Ok(())
+}
+
 pub fn gwei_to_wei(gwei: u128) -> Result<u128> {
     let wei_per_gwei: u128 = 10u128.pow(9);
     match gwei.checked_mul(wei_per_gwei) {
@@ -233,3 +1341,278 @@ pub fn gwei_to_wei(gwei: u128) -> Result<u128> {
         None => bail!("overflow occurred while converting gwei to wei"),
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        compression::decompress_wasm,
+        prelude::{extract_compressed_wasm, extract_compression},
+        util::text::decode0x,
+        Apis, Opts,
+    };
+    use clap::Parser;
+    use ethers::utils::rlp::Rlp;
+
+    #[tokio::test]
+    async fn test_init_code_matches_check_calldata() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let wasm = dir.path().join("contract.wasm");
+        std::fs::write(&wasm, wasmer::wat2wasm(b"(module (func) (func))")?)?;
+        let wasm = wasm.to_string_lossy();
+        let modes = [
+            (vec!["--compression", "brotli"], Compression::Brotli),
+            (vec!["--compression", "zstd"], Compression::Zstd),
+            (vec!["--no-compress"], Compression::None),
+        ];
+        for (flags, compression) in modes {
+            let args = [
+                "cargo-stylus",
+                "deploy",
+                "--endpoint=http://localhost:8547",
+                "--private-key=0x01",
+                "--size-only",
+                "--wasm-file",
+                &wasm,
+            ];
+            let opts = Opts::try_parse_from(args.into_iter().chain(flags))?;
+            let Apis::Deploy(cfg) = opts.command else {
+                panic!("expected the deploy command");
+            };
+            let contract = check::check(&cfg.check_config).await?;
+            let init_code = cfg.init_code(&contract, &[])?;
+            assert_eq!(
+                format!("0x{}", hex::encode(&init_code)),
+                contract.report().deployment_calldata
+            );
+            assert_eq!(extract_compression(&init_code)?, compression);
+            let code = extract_compressed_wasm(&init_code);
+            assert_eq!(decompress_wasm(&code, compression)?, contract.code());
+
+            let args = [0x11; 32];
+            let with_args = cfg.init_code(&contract, &args)?;
+            assert_eq!(with_args, [init_code, args.to_vec()].concat());
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_sign_offline_pays_the_tip() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let wasm = dir.path().join("contract.wasm");
+        std::fs::write(&wasm, wasmer::wat2wasm(b"(module (func))")?)?;
+        let out = dir.path().join("tx.hex");
+        let args = [
+            "cargo-stylus",
+            "deploy",
+            "--endpoint=http://localhost:8547",
+            "--private-key=0x0000000000000000000000000000000000000000000000000000000000000001",
+            "--size-only",
+            "--wasm-file",
+            &wasm.to_string_lossy(),
+            "--offline",
+            "--out",
+            &out.to_string_lossy(),
+            "--nonce=3",
+            "--gas-limit=1000000",
+            "--chain-id=412346",
+            "--max-fee-per-gas-gwei=10",
+        ];
+        assert!(Opts::try_parse_from(args).is_err());
+        let args = args
+            .into_iter()
+            .chain(["--max-priority-fee-per-gas-gwei=2"]);
+        let Apis::Deploy(cfg) = Opts::try_parse_from(args)?.command else {
+            panic!("expected the deploy command");
+        };
+        let contract = check::check(&cfg.check_config).await?;
+        let wallet = cfg.auth.signer().await?;
+        cfg.sign_offline(&contract, &[], wallet).await?;
+
+        let raw = decode0x(std::fs::read_to_string(&out)?)?;
+        let (tx, _) = TypedTransaction::decode_signed(&Rlp::new(&raw))?;
+        let TypedTransaction::Eip1559(tx) = tx else {
+            panic!("expected an EIP-1559 tx");
+        };
+        assert_eq!(tx.max_fee_per_gas, Some(U256::from(10_000_000_000u64)));
+        assert_eq!(
+            tx.max_priority_fee_per_gas,
+            Some(U256::from(2_000_000_000u64))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_dirty_paths() {
+        assert!(dirty_paths("").is_empty());
+        let status = " M src/lib.rs\n?? notes.txt\nR  a.rs -> b.rs\n";
+        assert_eq!(
+            dirty_paths(status),
+            vec!["src/lib.rs", "notes.txt", "a.rs -> b.rs"]
+        );
+    }
+
+    #[test]
+    fn test_bump_by_percent() -> Result<()> {
+        assert_eq!(
+            bump_by_percent(U256::from(1_000u64), 0)?,
+            U256::from(1_000u64)
+        );
+        assert_eq!(
+            bump_by_percent(U256::from(1_000u64), 10)?,
+            U256::from(1_100u64)
+        );
+        assert!(bump_by_percent(U256::MAX, 10).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_fee_with_headroom() -> Result<()> {
+        let gwei = U256::exp10(9);
+        let fee = fee_with_headroom(gwei * 10, 2.0, gwei)?;
+        assert_eq!(fee, gwei * 21);
+        let fee = fee_with_headroom(U256::from(100), 1.25, U256::from(3))?;
+        assert_eq!(fee, U256::from(128));
+        assert!(fee_with_headroom(gwei, 0.5, gwei).is_err());
+        assert!(fee_with_headroom(gwei, f64::NAN, gwei).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_describe_calls() {
+        assert_eq!(describe_calls(&[]), "no earlier calls succeeded");
+        let call = CallReport {
+            name: "post-deploy call".into(),
+            tx_hash: H256::repeat_byte(0xab),
+            gas_used: None,
+        };
+        let expected = format!("after post-deploy call in tx {:?} succeeded", call.tx_hash);
+        assert_eq!(describe_calls(&[call]), expected);
+    }
+
+    #[test]
+    fn test_check_chain_id() {
+        assert!(check_chain_id(1234, 1234).is_ok());
+        let err = check_chain_id(1234, 1).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "expected chain 1234 but the endpoint reports chain 1"
+        );
+    }
+
+    #[test]
+    fn test_encode_constructor_args() -> Result<()> {
+        let constructor = Constructor::parse("constructor(uint256 supply, address owner)")?;
+        let owner = "0x000000000000000000000000000000000000dEaD".to_string();
+        let args = vec!["42".to_string(), owner];
+        let encoded = encode_constructor_args(Some(&constructor), &args)?;
+        assert_eq!(encoded.len(), 64);
+        assert_eq!(encoded[31], 42);
+        assert_eq!(encoded[62..], [0xde, 0xad]);
+
+        assert!(encode_constructor_args(Some(&constructor), &args[..1]).is_err());
+        assert!(encode_constructor_args(None, &args).is_err());
+        assert!(encode_constructor_args(None, &[])?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_codehash() {
+        let code = b"\0asm";
+        let codehash = H256(ethers::utils::keccak256(code));
+        assert!(check_codehash(codehash, code).is_ok());
+        let err = check_codehash(H256::zero(), code).unwrap_err();
+        assert!(err.to_string().starts_with("expected codehash 0x0000"));
+    }
+
+    #[test]
+    fn test_gas_breakdown() {
+        let data = [0, 0, 1, 2];
+        let rows = gas_breakdown(&data, true, &[], U256::from(60_000));
+        let get = |key| rows.iter().find(|(k, _)| *k == key).unwrap().1.clone();
+        assert_eq!(get("init code words"), "2 gas (0.0%)");
+        assert_eq!(get("calldata zero bytes"), "8 gas (0.0%)");
+        assert_eq!(get("calldata non-zero bytes"), "32 gas (0.1%)");
+        assert_eq!(get("execution"), "6958 gas (11.6%)");
+
+        let item = AccessListItem {
+            address: H160::zero(),
+            storage_keys: vec![H256::zero(); 2],
+        };
+        let rows = gas_breakdown(&[], false, &[item], U256::from(30_000));
+        let names: Vec<_> = rows.iter().map(|(name, _)| *name).collect();
+        assert_eq!(
+            names,
+            vec![
+                "base",
+                "calldata zero bytes",
+                "calldata non-zero bytes",
+                "access list",
+                "execution",
+                "total"
+            ]
+        );
+        assert_eq!(rows[3].1, "6200 gas (20.7%)");
+    }
+
+    #[test]
+    fn test_factory_calldata() {
+        let selector = ethers::utils::id(FACTORY_DEPLOY_SIGNATURE);
+        let calldata = factory_calldata(selector, vec![0xaa; 3], [0xbb; 32]);
+        assert_eq!(calldata[..4], selector);
+        // Head: offset of the bytes and the salt. Tail: length then padded contents.
+        assert_eq!(calldata.len(), 4 + 32 * 4);
+        assert_eq!(U256::from_big_endian(&calldata[4..36]), U256::from(64));
+        assert_eq!(calldata[36..68], [0xbb; 32]);
+        assert_eq!(U256::from_big_endian(&calldata[68..100]), U256::from(3));
+        assert_eq!(calldata[100..103], [0xaa; 3]);
+    }
+
+    #[test]
+    fn test_create2_calldata() {
+        let salt = H256::repeat_byte(0x11);
+        let calldata = create2_calldata(salt, vec![0xaa; 3]);
+        assert_eq!(calldata[..32], [0x11; 32]);
+        assert_eq!(calldata[32..], [0xaa; 3]);
+    }
+
+    #[test]
+    fn test_create3() {
+        let salt = H256::repeat_byte(0x11);
+        let calldata = create3_calldata(salt, vec![0xaa; 3]);
+        assert_eq!(calldata[..4], ethers::utils::id(CREATE3_DEPLOY_SIGNATURE));
+        assert_eq!(calldata[4..36], [0x11; 32]);
+        assert_eq!(U256::from_big_endian(&calldata[36..68]), U256::from(64));
+        assert_eq!(U256::from_big_endian(&calldata[68..100]), U256::from(3));
+        assert_eq!(calldata[100..103], [0xaa; 3]);
+
+        // The address is per sender, so others can't take it by reusing the salt.
+        let factory = H160::repeat_byte(0xf0);
+        let address = create3_address(factory, H160::repeat_byte(1), salt);
+        assert_eq!(
+            address,
+            create3_address(factory, H160::repeat_byte(1), salt)
+        );
+        assert_ne!(
+            address,
+            create3_address(factory, H160::repeat_byte(2), salt)
+        );
+    }
+
+    #[test]
+    fn test_check_funds() {
+        let account = H160::zero();
+        let data_fee = AU256::from(1_000u64);
+        let value = U256::from(500u64);
+
+        assert!(check_funds(account, U256::from(1_500u64), data_fee, value).is_ok());
+        assert!(check_funds(account, U256::from(2_000u64), data_fee, value).is_ok());
+
+        let err = check_funds(account, U256::from(1_499u64), data_fee, value).unwrap_err();
+        let StylusError::InsufficientFunds { have, need, .. } = err else {
+            panic!("expected insufficient funds, got {err}");
+        };
+        assert_eq!(have, AU256::from(1_499u64));
+        assert_eq!(need, AU256::from(1_500u64));
+    }
+}
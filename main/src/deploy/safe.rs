@@ -0,0 +1,176 @@
+// Copyright 2023-2024, Offchain Labs, Inc.
+// For licensing, see https://github.com/OffchainLabs/cargo-stylus/blob/main/licenses/COPYRIGHT.md
+
+use super::SignerClient;
+use crate::error::StylusError;
+use crate::util::sys;
+use ethers::{
+    abi::{encode, Token},
+    providers::Middleware,
+    signers::Signer,
+    types::{transaction::eip2718::TypedTransaction, Eip1559TransactionRequest, H160, H256, U256},
+    utils::{id, keccak256, to_checksum},
+};
+use eyre::{bail, Result, WrapErr};
+use serde::Serialize;
+
+const SAFE_TX_SIGNATURE: &str = "SafeTx(address to,uint256 value,bytes data,uint8 operation,uint256 safeTxGas,uint256 baseGas,uint256 gasPrice,address gasToken,address refundReceiver,uint256 nonce)";
+const SAFE_DOMAIN_SIGNATURE: &str = "EIP712Domain(uint256 chainId,address verifyingContract)";
+const PERFORM_CREATE_SIGNATURE: &str = "performCreate(uint256,bytes)";
+
+/// Safe operation that delegatecalls the target, letting `CreateCall` deploy as the Safe.
+const DELEGATECALL: u8 = 1;
+
+/// A multisig tx as the Safe transaction service accepts it.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Proposal {
+    to: String,
+    value: String,
+    data: String,
+    operation: u8,
+    safe_tx_gas: String,
+    base_gas: String,
+    gas_price: String,
+    gas_token: String,
+    refund_receiver: String,
+    nonce: String,
+    contract_transaction_hash: String,
+    sender: String,
+    signature: String,
+    origin: &'static str,
+}
+
+/// Proposes deploying the init code from the Safe, through its `CreateCall` library, to
+/// the Safe transaction service. The proposal is signed by the client's signer, who must
+/// be an owner, and the other owners approve it from there. Returns the safeTxHash.
+pub async fn propose_deployment(
+    service: &str,
+    safe: H160,
+    create_call: H160,
+    init_code: Vec<u8>,
+    client: &SignerClient,
+    proxy: Option<&str>,
+) -> Result<H256> {
+    let data = create_call_data(init_code);
+    let nonce = safe_nonce(safe, client).await?;
+    let chain_id = client.signer().chain_id();
+    let safe_tx_hash = safe_tx_hash(chain_id, safe, create_call, &data, nonce);
+
+    let mut signature = client
+        .signer()
+        .sign_message(safe_tx_hash.as_bytes())
+        .await
+        .wrap_err("failed to sign Safe tx")?;
+    // Safe tells signatures over the prefixed message apart by an offset v.
+    signature.v += 4;
+
+    let zero = to_checksum(&H160::zero(), None);
+    let proposal = Proposal {
+        to: to_checksum(&create_call, None),
+        value: "0".into(),
+        data: format!("0x{}", hex::encode(&data)),
+        operation: DELEGATECALL,
+        safe_tx_gas: "0".into(),
+        base_gas: "0".into(),
+        gas_price: "0".into(),
+        gas_token: zero.clone(),
+        refund_receiver: zero,
+        nonce: nonce.to_string(),
+        contract_transaction_hash: format!("{safe_tx_hash:?}"),
+        sender: to_checksum(&client.address(), None),
+        signature: format!("0x{signature}"),
+        origin: "cargo-stylus",
+    };
+    let url = format!(
+        "{}/api/v1/safes/{}/multisig-transactions/",
+        service.trim_end_matches('/'),
+        to_checksum(&safe, None)
+    );
+    let response = sys::http_client_builder(proxy)?
+        .build()
+        .wrap_err("failed to init http client")?
+        .post(url)
+        .json(&proposal)
+        .send()
+        .await
+        .wrap_err("failed to reach the Safe transaction service")?;
+    let status = response.status();
+    if !status.is_success() {
+        let body = response.text().await.unwrap_or_default();
+        bail!("Safe transaction service rejected the proposal ({status}): {body}");
+    }
+    Ok(safe_tx_hash)
+}
+
+/// Encodes a call to `CreateCall.performCreate`, deploying the init code with no value.
+fn create_call_data(init_code: Vec<u8>) -> Vec<u8> {
+    let args = encode(&[Token::Uint(U256::zero()), Token::Bytes(init_code)]);
+    [id(PERFORM_CREATE_SIGNATURE).as_slice(), &args].concat()
+}
+
+/// Reads the nonce the Safe's next tx must use.
+async fn safe_nonce(safe: H160, client: &SignerClient) -> Result<U256> {
+    let tx = Eip1559TransactionRequest::new()
+        .to(safe)
+        .data(id("nonce()").to_vec());
+    let output = client
+        .call(&TypedTransaction::Eip1559(tx), None)
+        .await
+        .map_err(|e| StylusError::Rpc(format!("failed to get Safe nonce: {e}")))?;
+    if output.len() < 32 {
+        bail!("{safe:?} did not return a nonce, is it a Safe?");
+    }
+    Ok(U256::from_big_endian(&output[..32]))
+}
+
+/// Computes the EIP-712 hash of a Safe tx without gas refunds, which owners sign.
+fn safe_tx_hash(chain_id: u64, safe: H160, to: H160, data: &[u8], nonce: U256) -> H256 {
+    let domain = keccak256(encode(&[
+        Token::FixedBytes(keccak256(SAFE_DOMAIN_SIGNATURE).to_vec()),
+        Token::Uint(chain_id.into()),
+        Token::Address(safe),
+    ]));
+    let safe_tx = keccak256(encode(&[
+        Token::FixedBytes(keccak256(SAFE_TX_SIGNATURE).to_vec()),
+        Token::Address(to),
+        Token::Uint(U256::zero()),
+        Token::FixedBytes(keccak256(data).to_vec()),
+        Token::Uint(DELEGATECALL.into()),
+        Token::Uint(U256::zero()),
+        Token::Uint(U256::zero()),
+        Token::Uint(U256::zero()),
+        Token::Address(H160::zero()),
+        Token::Address(H160::zero()),
+        Token::Uint(nonce),
+    ]));
+    H256(keccak256(
+        [[0x19, 0x01].as_slice(), &domain, &safe_tx].concat(),
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_create_call_data() {
+        let data = create_call_data(vec![0xaa; 3]);
+        assert_eq!(data[..4], id(PERFORM_CREATE_SIGNATURE));
+        assert_eq!(U256::from_big_endian(&data[4..36]), U256::zero());
+        assert_eq!(U256::from_big_endian(&data[36..68]), U256::from(64));
+        assert_eq!(U256::from_big_endian(&data[68..100]), U256::from(3));
+        assert_eq!(data[100..103], [0xaa; 3]);
+    }
+
+    #[test]
+    fn test_safe_tx_hash() {
+        let safe = H160::repeat_byte(1);
+        let to = H160::repeat_byte(2);
+        let hash = safe_tx_hash(1, safe, to, &[0xaa], U256::zero());
+        // The hash commits to the chain, the Safe and its nonce, so it can't be replayed.
+        assert_ne!(hash, safe_tx_hash(2, safe, to, &[0xaa], U256::zero()));
+        assert_ne!(hash, safe_tx_hash(1, to, to, &[0xaa], U256::zero()));
+        assert_ne!(hash, safe_tx_hash(1, safe, to, &[0xaa], U256::one()));
+    }
+}
@@ -0,0 +1,89 @@
+// Copyright 2023-2024, Offchain Labs, Inc.
+// For licensing, see https://github.com/OffchainLabs/cargo-stylus/blob/main/licenses/COPYRIGHT.md
+
+use crate::{
+    macros::*,
+    util::color::{Color, DebugColor},
+};
+use ethers::types::H160;
+use eyre::{bail, eyre, Result};
+use revm::{
+    db::{CacheDB, EmptyDB},
+    primitives::{AccountInfo, Address, ExecutionResult, Output, TxKind, U256},
+    Evm,
+};
+
+/// Gas limit given to the simulated deployment.
+const SIMULATION_GAS_LIMIT: u64 = 30_000_000;
+
+/// Runs the deployment calldata through an in-process EVM, independent of the RPC.
+/// Returns the gas used, bailing if the deployment would revert or halt.
+pub fn simulate_deployment(sender: H160, init_code: &[u8]) -> Result<u64> {
+    let caller = Address::from(sender.0);
+    let mut db = CacheDB::new(EmptyDB::default());
+    db.insert_account_info(
+        caller,
+        AccountInfo {
+            balance: U256::MAX,
+            ..Default::default()
+        },
+    );
+    let mut evm = Evm::builder()
+        .with_db(db)
+        .modify_cfg_env(|cfg| {
+            // The compressed size is already checked against the network's limit.
+            cfg.limit_contract_code_size = Some(usize::MAX);
+        })
+        .modify_block_env(|block| block.gas_limit = U256::from(SIMULATION_GAS_LIMIT))
+        .modify_tx_env(|tx| {
+            tx.caller = caller;
+            tx.transact_to = TxKind::Create;
+            tx.data = init_code.to_vec().into();
+            tx.gas_limit = SIMULATION_GAS_LIMIT;
+            tx.gas_price = U256::ZERO;
+        })
+        .build();
+    let result = evm
+        .transact()
+        .map_err(|e| eyre!("failed to simulate deployment: {e:?}"))?
+        .result;
+
+    match result {
+        ExecutionResult::Success {
+            gas_used,
+            output: Output::Create(code, _),
+            ..
+        } => {
+            greyln!(
+                "simulated deployment succeeded: {} gas, {} B of code",
+                gas_used.lavender(),
+                code.len()
+            );
+            Ok(gas_used)
+        }
+        ExecutionResult::Success { .. } => bail!("simulated deployment did not create a contract"),
+        ExecutionResult::Revert { gas_used, output } => bail!(
+            "simulated deployment reverted after {gas_used} gas: 0x{}",
+            hex::encode(output)
+        ),
+        ExecutionResult::Halt { reason, gas_used } => bail!(
+            "simulated deployment halted after {gas_used} gas: {}",
+            reason.debug_red()
+        ),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{compression::Compression, prelude::contract_deployment_calldata};
+
+    #[test]
+    fn test_simulate_deployment() -> Result<()> {
+        let code = b"\0asm\x01\0\0\0 trivial code blob".to_vec();
+        let init_code = contract_deployment_calldata(&code, Compression::Brotli)?;
+        assert!(simulate_deployment(H160::zero(), &init_code)? > 0);
+        assert!(simulate_deployment(H160::zero(), &[0xfe]).is_err());
+        Ok(())
+    }
+}
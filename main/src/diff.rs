@@ -0,0 +1,41 @@
+// Copyright 2023-2024, Offchain Labs, Inc.
+// For licensing, see https://github.com/OffchainLabs/cargo-stylus/blob/main/licenses/COPYRIGHT.md
+
+use crate::util::color::{Color, DebugColor};
+use crate::{check, DiffConfig};
+use ethers::middleware::Middleware;
+use eyre::{bail, Result, WrapErr};
+
+/// Builds the contract locally and compares it with the code deployed at an address,
+/// to tell whether a redeploy is needed.
+pub async fn diff(cfg: DiffConfig) -> Result<()> {
+    let contract = check::check(&cfg.check_config).await?;
+    let provider = cfg.check_config.common_cfg.new_provider().await?;
+    let onchain = provider
+        .get_code(cfg.address, None)
+        .await
+        .wrap_err("failed to get deployed code")?;
+    if onchain.is_empty() {
+        bail!("no code at address {}", cfg.address.debug_red());
+    }
+
+    // The prelude returns the compressed code, so that is what ends up onchain.
+    let local = contract.compressed_code();
+    if local == onchain.as_ref() {
+        println!(
+            "Identical - code at {} matches the local build",
+            cfg.address.debug_lavender()
+        );
+        return Ok(());
+    }
+    let delta = local.len() as i64 - onchain.len() as i64;
+    println!(
+        "{} - code at {} differs from the local build",
+        "DIFFERENT".red(),
+        cfg.address.debug_lavender()
+    );
+    println!("Local code length {}", local.len());
+    println!("Deployed code length {}", onchain.len());
+    println!("Length delta {delta:+}");
+    Ok(())
+}
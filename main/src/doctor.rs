@@ -0,0 +1,99 @@
+// Copyright 2023-2024, Offchain Labs, Inc.
+// For licensing, see https://github.com/OffchainLabs/cargo-stylus/blob/main/licenses/COPYRIGHT.md
+
+use crate::{
+    constants::{RUST_TARGET, TOOLCHAIN_FILE_NAME},
+    project::{ensure_toolchain_installed, extract_toolchain_channel},
+    util::{color::Color, sys},
+};
+use eyre::{bail, Result};
+use std::{path::PathBuf, process::Command};
+
+/// Checks the environment can build Stylus projects, printing a pass or fail for each
+/// requirement along with how to fix it.
+pub fn doctor() -> Result<()> {
+    let channel = extract_toolchain_channel(&PathBuf::from(TOOLCHAIN_FILE_NAME));
+    let checks = [
+        (
+            "rustc",
+            tool_installed("rustc", "install Rust via https://rustup.rs"),
+        ),
+        (
+            "cargo",
+            tool_installed("cargo", "install Rust via https://rustup.rs"),
+        ),
+        (
+            "git",
+            tool_installed("git", "install git via https://git-scm.com"),
+        ),
+        ("project toolchain", project_toolchain(&channel)),
+        ("wasm target", wasm_target(&channel)),
+    ];
+    let mut failures = 0;
+    for (name, check) in checks {
+        match check {
+            Ok(()) => println!("{} {name}", "pass".mint()),
+            Err(hint) => {
+                failures += 1;
+                println!("{} {name}: {hint}", "fail".pink());
+            }
+        }
+    }
+    if failures > 0 {
+        bail!("{failures} environment checks failed");
+    }
+    Ok(())
+}
+
+fn tool_installed(program: &str, hint: &str) -> Result<(), String> {
+    match sys::command_exists(program) {
+        true => Ok(()),
+        false => Err(format!("{program} not found, {hint}")),
+    }
+}
+
+fn project_toolchain(channel: &Result<String>) -> Result<(), String> {
+    let Ok(channel) = channel else {
+        return Err(format!(
+            "no toolchain channel found, run from a project with a {TOOLCHAIN_FILE_NAME}"
+        ));
+    };
+    ensure_toolchain_installed(channel, false).map_err(|e| e.to_string())
+}
+
+fn wasm_target(channel: &Result<String>) -> Result<(), String> {
+    let mut rustup = Command::new("rustup");
+    if let Ok(channel) = channel {
+        rustup.arg(format!("+{channel}"));
+    }
+    let hint = match channel {
+        Ok(channel) => format!("run rustup target add {RUST_TARGET} --toolchain {channel}"),
+        Err(_) => format!("run rustup target add {RUST_TARGET}"),
+    };
+    let output = rustup
+        .args(["target", "list", "--installed"])
+        .output()
+        .map_err(|e| format!("failed to run rustup: {e}"))?;
+    match target_installed(&String::from_utf8_lossy(&output.stdout), RUST_TARGET) {
+        true => Ok(()),
+        false => Err(format!("{RUST_TARGET} not installed, {hint}")),
+    }
+}
+
+/// Whether the output of `rustup target list --installed` includes the target.
+fn target_installed(list: &str, target: &str) -> bool {
+    list.lines().any(|line| line.trim() == target)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_target_installed() {
+        let list = "wasm32-unknown-unknown\nx86_64-unknown-linux-gnu\n";
+        assert!(target_installed(list, RUST_TARGET));
+        assert!(!target_installed("x86_64-unknown-linux-gnu\n", RUST_TARGET));
+        assert!(!target_installed("", RUST_TARGET));
+    }
+}
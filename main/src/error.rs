@@ -0,0 +1,52 @@
+// Copyright 2023-2024, Offchain Labs, Inc.
+// For licensing, see https://github.com/OffchainLabs/cargo-stylus/blob/main/licenses/COPYRIGHT.md
+
+use crate::util::color::{Color, DebugColor};
+use alloy_primitives::U256;
+use ethers::types::{H160, H256};
+
+/// Link shown to users whose account can't cover a deployment.
+pub const FUNDING_GUIDE_URL: &str = "https://docs.pharosnetwork.xyz/developer-guides";
+
+/// Errors that callers may want to match on. These are raised through `eyre`,
+/// so embedders can recover them with `Report::downcast_ref::<StylusError>()`.
+#[derive(thiserror::Error, Debug)]
+pub enum StylusError {
+    #[error("failed to build wasm: {0}")]
+    Build(String),
+    #[error("rpc request failed: {0}")]
+    Rpc(String),
+    #[error(
+        "{name} tx reverted {}{}",
+        .tx_hash.debug_red(),
+        .reason.as_ref().map(|r| format!(": {}", r.red())).unwrap_or_default()
+    )]
+    Revert {
+        name: String,
+        tx_hash: H256,
+        reason: Option<String>,
+    },
+    #[error(
+        "failed to get receipt for {name} tx {}, it may still be pending",
+        .tx_hash.debug_yellow()
+    )]
+    MissingReceipt { name: String, tx_hash: H256 },
+    #[error("{name} tx ran out of gas {}", .tx_hash.debug_red())]
+    OutOfGas { name: String, tx_hash: H256 },
+    #[error(
+        "not enough funds in account {} to pay for data fee\n\
+         balance {} < {} wei\n\
+         please see the Quickstart guide for funding new accounts:\n{}",
+        .account.red(),
+        .have.red(),
+        .need.red(),
+        FUNDING_GUIDE_URL.yellow()
+    )]
+    InsufficientFunds {
+        account: H160,
+        have: U256,
+        need: U256,
+    },
+    #[error("{0}")]
+    Validation(String),
+}
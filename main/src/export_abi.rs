@@ -5,6 +5,7 @@ use crate::macros::*;
 use crate::util::{color::Color, sys};
 use alloy_json_abi::Constructor;
 use eyre::{bail, Result, WrapErr};
+use serde_json::Value;
 use std::{
     io::Write,
     path::PathBuf,
@@ -12,7 +13,7 @@ use std::{
 };
 
 /// Exports Solidity ABIs by running the contract natively.
-pub fn export_abi(file: Option<PathBuf>, json: bool) -> Result<()> {
+pub fn export_abi(file: Option<PathBuf>, json: bool, public_only: bool) -> Result<()> {
     if json && !sys::command_exists("solc") {
         let link = "https://docs.soliditylang.org/en/latest/installing-solidity.html".red();
         bail!("solc not found. Please see\n{link}");
@@ -29,7 +30,7 @@ pub fn export_abi(file: Option<PathBuf>, json: bool) -> Result<()> {
 
     if !output.status.success() {
         let out = String::from_utf8_lossy(&output.stdout);
-        let out = (out != "")
+        let out = (!out.is_empty())
             .then_some(format!(": {out}"))
             .unwrap_or_default();
         egreyln!("failed to run contract {out}");
@@ -59,6 +60,13 @@ pub fn export_abi(file: Option<PathBuf>, json: bool) -> Result<()> {
     if !output.status.success() {
         // If stderr has content, suppress and write "[]"
         out.write_all(b"[]\n")?;
+    } else if public_only {
+        let abi = String::from_utf8_lossy(&output.stdout);
+        let abi = match json {
+            true => public_json_abi(&abi)?,
+            false => public_solidity_abi(&abi),
+        };
+        out.write_all(abi.as_bytes())?;
     } else {
         // If no error, write the actual output (stdout)
         out.write_all(&output.stdout)?;
@@ -88,7 +96,7 @@ fn run_export(command: &str) -> Result<Vec<u8>> {
         .output()?;
     if !output.status.success() {
         let out = String::from_utf8_lossy(&output.stdout);
-        let out = (out != "")
+        let out = (!out.is_empty())
             .then_some(format!(": {out}"))
             .unwrap_or_default();
         egreyln!("failed to run contract {out}");
@@ -97,6 +105,46 @@ fn run_export(command: &str) -> Result<Vec<u8>> {
     Ok(output.stdout)
 }
 
+/// Keeps only the entries of solc's JSON ABI output that are callable through a selector,
+/// and the constructor.
+fn public_json_abi(output: &str) -> Result<String> {
+    let mut filtered = String::new();
+    for line in output.lines() {
+        if line.trim_start().starts_with('[') {
+            let mut abi: Vec<Value> = serde_json::from_str(line)?;
+            abi.retain(|item| matches!(item["type"].as_str(), Some("function" | "constructor")));
+            filtered.push_str(&serde_json::to_string(&abi)?);
+        } else {
+            filtered.push_str(line);
+        }
+        filtered.push('\n');
+    }
+    Ok(filtered)
+}
+
+/// Drops the events, errors and non-public functions from a Solidity interface.
+fn public_solidity_abi(interface: &str) -> String {
+    let mut filtered = String::new();
+    let mut last_blank = false;
+    for line in interface.lines() {
+        let decl = line.trim_start();
+        let callable = decl.contains(" external") || decl.contains(" public");
+        let dropped = decl.starts_with("event ")
+            || decl.starts_with("error ")
+            || decl.starts_with("fallback")
+            || decl.starts_with("receive")
+            || (decl.starts_with("function ") && !callable);
+        let blank = decl.is_empty();
+        if dropped || (blank && last_blank) {
+            continue;
+        }
+        last_blank = blank;
+        filtered.push_str(line);
+        filtered.push('\n');
+    }
+    filtered
+}
+
 fn parse_constructor(signature: &str) -> Result<Option<Constructor>> {
     let signature = signature.trim();
     if !signature.starts_with("constructor") {
@@ -115,6 +163,49 @@ mod tests {
     use super::*;
     use alloy_json_abi::Param;
 
+    #[test]
+    fn test_public_solidity_abi() {
+        let interface = "interface IToken  {
+    event Transfer(address indexed from, address indexed to, uint256 value);
+
+    error Unauthorized();
+
+    function balanceOf(address owner) external view returns (uint256);
+
+    function helper(uint256 x) internal returns (uint256);
+
+    function transfer(address to, uint256 value) external returns (bool);
+}
+";
+        let expected = "interface IToken  {
+
+    function balanceOf(address owner) external view returns (uint256);
+
+    function transfer(address to, uint256 value) external returns (bool);
+}
+";
+        assert_eq!(public_solidity_abi(interface), expected);
+    }
+
+    #[test]
+    fn test_public_json_abi() -> Result<()> {
+        let output = r#"
+======= <stdin>:IToken =======
+Contract JSON ABI
+[{"type":"event","name":"Transfer"},{"type":"function","name":"balanceOf"},{"type":"error","name":"Unauthorized"}]
+"#;
+        let filtered = public_json_abi(output)?;
+        let lines: Vec<_> = filtered.lines().collect();
+        assert_eq!(
+            lines[..3],
+            ["", "======= <stdin>:IToken =======", "Contract JSON ABI"]
+        );
+        let abi: Value = serde_json::from_str(lines[3])?;
+        let expected: Value = serde_json::from_str(r#"[{"type":"function","name":"balanceOf"}]"#)?;
+        assert_eq!(abi, expected);
+        Ok(())
+    }
+
     #[test]
     fn parse_constructors() {
         let test_cases = vec![
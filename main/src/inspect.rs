@@ -0,0 +1,111 @@
+// Copyright 2023-2024, Offchain Labs, Inc.
+// For licensing, see https://github.com/OffchainLabs/cargo-stylus/blob/main/licenses/COPYRIGHT.md
+
+use crate::{
+    compression,
+    prelude::{
+        deployment_prelude_len, extract_compressed_wasm, extract_compression,
+        extract_constructor_args, has_deployment_prelude,
+    },
+    util::{color::DebugColor, sys, text},
+};
+use ethers::{providers::Middleware, types::H256, utils::keccak256};
+use eyre::{eyre, Result};
+
+/// The magic bytes every WASM module starts with.
+const WASM_MAGIC: &[u8] = b"\0asm";
+
+/// Prints a readable summary of a deployment tx: its prelude, compression and code.
+pub async fn inspect(endpoint: &str, deployment_tx: H256) -> Result<()> {
+    let provider = sys::new_provider(endpoint).await?;
+    let tx = provider
+        .get_transaction(deployment_tx)
+        .await
+        .map_err(|e| eyre!("RPC failed: {e}"))?
+        .ok_or_else(|| eyre!("tx {} not found", deployment_tx.debug_red()))?;
+    print!("{}", text::format_table(&summarize(&tx.input)?));
+    Ok(())
+}
+
+/// Interprets deployment calldata as rows of a summary.
+fn summarize(calldata: &[u8]) -> Result<Vec<(&'static str, String)>> {
+    let code = extract_compressed_wasm(calldata);
+    let mut rows = vec![];
+    if has_deployment_prelude(calldata) {
+        let version = calldata[deployment_prelude_len() - 1];
+        rows.push(("prelude version", format!("{version:#04x}")));
+        let compression = extract_compression(calldata).ok();
+        rows.push((
+            "compression",
+            compression.map_or("unknown".to_string(), |c| c.to_string()),
+        ));
+    } else {
+        rows.push(("prelude", "none, raw code".to_string()));
+    }
+    rows.push(("code size", format!("{} B", code.len())));
+    let args = extract_constructor_args(calldata);
+    if !args.is_empty() {
+        rows.push(("constructor args", format!("0x{}", hex::encode(args))));
+    }
+
+    // Uncompressed deployments carry the processed WASM as-is, so only decompress what
+    // isn't WASM already.
+    let wasm = match code.starts_with(WASM_MAGIC) {
+        true => Some(code.clone()),
+        false => extract_compression(calldata)
+            .ok()
+            .and_then(|compression| compression::decompress_wasm(&code, compression).ok()),
+    };
+    match &wasm {
+        Some(wasm) => {
+            rows.push(("wasm size", format!("{} B", wasm.len())));
+            let project_hash = compression::read_project_hash(wasm)?;
+            rows.push((
+                "project hash",
+                project_hash.map_or("none".to_string(), hex::encode),
+            ));
+        }
+        None => rows.push(("wasm size", "could not decode".to_string())),
+    }
+    rows.push((
+        "runtime code keccak",
+        format!("0x{}", hex::encode(keccak256(&code))),
+    ));
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{compression::Compression, prelude::deployment_calldata};
+
+    #[test]
+    fn test_summarize() -> Result<()> {
+        let wasm = compression::embed_project_hash(b"\0asm\x01\0\0\0", [0xab; 32])?;
+        let calldata = deployment_calldata(&wasm, Compression::Zstd, false)?;
+        let rows = summarize(&calldata)?;
+        let get = |key| {
+            rows.iter()
+                .find(|(k, _)| *k == key)
+                .map(|(_, v)| v.as_str())
+        };
+        assert_eq!(get("prelude version"), Some("0x01"));
+        assert_eq!(get("compression"), Some("zstd"));
+        assert_eq!(get("project hash"), Some(hex::encode([0xab; 32]).as_str()));
+        assert_eq!(
+            get("runtime code keccak"),
+            Some(format!("0x{}", hex::encode(keccak256(&wasm))).as_str())
+        );
+
+        let mut calldata = calldata;
+        calldata.extend([0x11; 32]);
+        let with_args = summarize(&calldata)?;
+        assert!(with_args.contains(&("code size", format!("{} B", wasm.len()))));
+        let args = format!("0x{}", hex::encode([0x11; 32]));
+        assert!(with_args.contains(&("constructor args", args)));
+
+        let rows = summarize(&deployment_calldata(&wasm, Compression::Zstd, true)?)?;
+        assert_eq!(rows[0], ("prelude", "none, raw code".to_string()));
+        Ok(())
+    }
+}
@@ -0,0 +1,118 @@
+// Copyright 2023-2024, Offchain Labs, Inc.
+// For licensing, see https://github.com/OffchainLabs/cargo-stylus/blob/main/licenses/COPYRIGHT.md
+
+//! Stores private keys in the OS keychain, be it the macOS Keychain, the Windows Credential
+//! Manager or the Secret Service via libsecret, so they never sit in files, env vars or
+//! shell history.
+
+use crate::{macros::*, util::color::Color, wallet::decode_private_key};
+use eyre::{bail, eyre, Result, WrapErr};
+use keyring::Entry;
+
+/// Keychain service the keys are stored under, one entry per key name.
+const SERVICE: &str = "cargo-stylus";
+
+/// Keychain entry holding the names of the stored keys, since keychains can't be listed.
+const INDEX_SERVICE: &str = "cargo-stylus-index";
+const INDEX_USER: &str = "keys";
+
+/// Prompts for a private key and stores it in the keychain under the name.
+pub fn store(name: &str) -> Result<()> {
+    validate_name(name)?;
+    let key = rpassword::prompt_password(format!("private key for {name}: "))
+        .wrap_err("failed to read private key")?;
+    let key = decode_private_key(&key)?;
+    Entry::new(SERVICE, name)?
+        .set_password(&hex::encode(key))
+        .wrap_err("failed to store key in the keychain")?;
+
+    let mut names = names()?;
+    if !names.iter().any(|n| n == name) {
+        names.push(name.to_string());
+        save_names(&names)?;
+    }
+    greyln!("stored key {} in the OS keychain", name.lavender());
+    Ok(())
+}
+
+/// Prints the names of the stored keys.
+pub fn list() -> Result<()> {
+    let names = names()?;
+    if names.is_empty() {
+        greyln!("no keys stored in the OS keychain");
+    }
+    for name in names {
+        println!("{name}");
+    }
+    Ok(())
+}
+
+/// Deletes the key stored under the name.
+pub fn remove(name: &str) -> Result<()> {
+    match Entry::new(SERVICE, name)?.delete_password() {
+        Ok(()) => {}
+        Err(keyring::Error::NoEntry) => bail!("no key named {name} in the OS keychain"),
+        Err(e) => return Err(e).wrap_err("failed to remove key from the keychain"),
+    }
+    let mut names = names()?;
+    names.retain(|n| n != name);
+    save_names(&names)?;
+    greyln!("removed key {} from the OS keychain", name.lavender());
+    Ok(())
+}
+
+/// Reads the hex-encoded private key stored under the name.
+pub fn load(name: &str) -> Result<String> {
+    match Entry::new(SERVICE, name)?.get_password() {
+        Ok(key) => Ok(key),
+        Err(keyring::Error::NoEntry) => Err(eyre!("no key named {name} in the OS keychain")),
+        Err(e) => Err(e).wrap_err("failed to read key from the keychain"),
+    }
+}
+
+fn names() -> Result<Vec<String>> {
+    match Entry::new(INDEX_SERVICE, INDEX_USER)?.get_password() {
+        Ok(index) => Ok(parse_index(&index)),
+        Err(keyring::Error::NoEntry) => Ok(vec![]),
+        Err(e) => Err(e).wrap_err("failed to read key names from the keychain"),
+    }
+}
+
+fn save_names(names: &[String]) -> Result<()> {
+    Entry::new(INDEX_SERVICE, INDEX_USER)?
+        .set_password(&names.join("\n"))
+        .wrap_err("failed to store key names in the keychain")
+}
+
+fn parse_index(index: &str) -> Vec<String> {
+    index
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Key names are stored one per line in the index, so must fit on one.
+fn validate_name(name: &str) -> Result<()> {
+    if name.trim().is_empty() || name.contains(['\n', '\r']) {
+        bail!("key names must be non-empty and on one line");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_index() -> Result<()> {
+        let names = vec!["deployer".to_string(), "ci key".to_string()];
+        assert_eq!(parse_index(&names.join("\n")), names);
+        assert!(parse_index("").is_empty());
+
+        validate_name("deployer")?;
+        assert!(validate_name("").is_err());
+        assert!(validate_name("a\nb").is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,25 @@
+// Copyright 2023-2024, Offchain Labs, Inc.
+// For licensing, see https://github.com/OffchainLabs/cargo-stylus/blob/main/licenses/COPYRIGHT.md
+
+//! Library interface to cargo stylus, for tools that build and deploy Stylus contracts
+//! without going through the CLI.
+//!
+//! The items re-exported here are the stable API and keep their signatures across minor
+//! releases. Everything else, including building and hashing projects, is internal to the
+//! `cargo stylus` binary and may change at any time.
+
+mod compression;
+mod prelude;
+
+/// Compressing a contract and embedding its project hash.
+pub use compression::{
+    compress_wasm, compress_wasm_bytes, decompress_wasm, embed_project_hash, read_project_hash,
+    Compression,
+};
+
+/// The EVM prelude that wraps compressed code into deployment calldata.
+pub use prelude::{
+    contract_deployment_calldata, deployment_calldata, extract_compressed_wasm,
+    extract_compression, extract_constructor_args, extract_contract_evm_deployment_prelude,
+    has_deployment_prelude, MAX_DEPLOYMENT_CODE_BYTES,
+};
@@ -3,8 +3,10 @@
 
 macro_rules! greyln {
     ($($msg:expr),*) => {{
-        let msg = format!($($msg),*);
-        println!("{}", msg.grey())
+        if !$crate::util::color::silenced() {
+            let msg = format!($($msg),*);
+            println!("{}", msg.grey())
+        }
     }};
 }
 
@@ -5,17 +5,31 @@
 #![cfg_attr(feature = "nightly", feature(test))]
 
 use alloy_primitives::{TxHash, B256};
-use clap::{ArgGroup, Args, CommandFactory, Parser, Subcommand};
+use clap::{ArgAction, ArgGroup, Args, CommandFactory, Parser, Subcommand};
+use compression::Compression;
+use deploy::{BlockTag, ReportFormat};
 use ethers::abi::Bytes;
-use ethers::types::{H160, U256};
+use ethers::providers::{Middleware, Provider};
+use ethers::types::{
+    transaction::{eip2718::TypedTransaction, eip2930::AccessList},
+    BlockNumber, H160, H256, U256,
+};
+use ethers::utils::{format_units, rlp::Rlp};
 use eyre::{bail, eyre, Context, Result};
+use macros::greyln;
 use std::{
     fmt,
+    future::Future,
     path::{Path, PathBuf},
+    time::Duration,
 };
 use tokio::runtime::Builder;
 use trace::Trace;
-use util::{color::Color, sys};
+use util::{
+    color::{Color, DebugColor},
+    sys,
+    timer::StepTimer,
+};
 
 // Conditional import for Unix-specific `CommandExt`
 #[cfg(unix)]
@@ -25,15 +39,26 @@ use std::{env, os::unix::process::CommandExt};
 #[cfg(windows)]
 use std::env;
 
+mod analyze;
 mod check;
+mod compression;
 mod constants;
 mod deploy;
+mod diff;
+mod doctor;
+mod error;
 mod export_abi;
 mod gen;
 mod hostio;
+mod inspect;
+mod keychain;
 mod macros;
 mod new;
+mod prelude;
 mod project;
+mod report;
+mod sarif;
+mod stylus_toml;
 mod trace;
 mod util;
 mod verify;
@@ -49,18 +74,36 @@ mod wallet;
 struct Opts {
     #[command(subcommand)]
     command: Apis,
+    /// Dotenv file to load environment variables like `PHAROS_RPC_URL` and
+    /// `PHAROS_PRIVATE_KEY` from. Defaults to `.env`, when present. Flags and variables
+    /// already set take precedence.
+    #[arg(long, global = true)]
+    env_file: Option<PathBuf>,
 }
 
 #[derive(Parser, Debug, Clone)]
+#[allow(clippy::large_enum_variant)]
 enum Apis {
     /// Create a new Stylus project.
     New {
         /// Project name.
         name: PathBuf,
+        /// HTTP or SOCKS proxy to clone the template through.
+        #[arg(long)]
+        proxy: Option<String>,
     },
     /// Initializes a Stylus project in the current directory.
-    Init {},
-    /// Export a Solidity ABI.
+    Init {
+        /// HTTP or SOCKS proxy to clone the template through.
+        #[arg(long)]
+        proxy: Option<String>,
+    },
+    /// List the built-in project templates.
+    ListTemplates,
+    /// Check the environment has everything needed to build Stylus projects.
+    Doctor,
+    /// Export a Solidity ABI. Only the contract's native ABI export runs, without building
+    /// the WASM or connecting to an endpoint.
     ExportAbi {
         /// The output file (defaults to stdout).
         #[arg(long)]
@@ -68,6 +111,10 @@ enum Apis {
         /// Write a JSON ABI instead using solc. Requires solc.
         #[arg(long)]
         json: bool,
+        /// Only export the functions callers can invoke through a selector, and the
+        /// constructor, leaving out events, errors and any non-public functions.
+        #[arg(long)]
+        abi_public_only: bool,
     },
     /// Check a contract.
     #[command(visible_alias = "c")]
@@ -75,9 +122,57 @@ enum Apis {
     /// Deploy a contract.
     #[command(visible_alias = "d")]
     Deploy(DeployConfig),
+    /// Compare a local build with the code deployed at an address.
+    Diff(DiffConfig),
+    /// Inspect the built WASM for common cost and performance pitfalls.
+    Analyze(AnalyzeConfig),
     /// Verify the deployment of a Stylus contract.
     #[command(visible_alias = "v")]
     Verify(VerifyConfig),
+    /// Check an RPC endpoint is healthy and print which chain it serves.
+    Ping {
+        /// RPC endpoint.
+        #[arg(short, long)]
+        endpoint: String,
+    },
+    /// Print a readable summary of a deployment tx's prelude, compression and code.
+    Inspect {
+        /// RPC endpoint.
+        #[arg(short, long)]
+        endpoint: String,
+        /// Hash of the deployment tx.
+        #[arg(long)]
+        deployment_tx: H256,
+    },
+    /// Print the project hash embedded in a WASM file.
+    Hash {
+        /// The WASM file to read.
+        #[arg(long)]
+        wasm_file: PathBuf,
+    },
+    /// Embed a project hash into a WASM built outside of cargo, for reproducible verification.
+    EmbedHash {
+        /// The WASM file to embed the hash into.
+        #[arg(long)]
+        wasm_file: PathBuf,
+        /// The 32-byte project hash, hex-encoded.
+        #[arg(long)]
+        hash: B256,
+        /// Where to write the WASM with the hash embedded.
+        #[arg(long)]
+        output: PathBuf,
+    },
+    /// Print the address a contract will be deployed at, without deploying it.
+    PredictAddress(PredictAddressArgs),
+    /// Submit a signed tx, e.g. from `deploy --offline`, and await its receipt.
+    Broadcast(BroadcastArgs),
+    /// List the deployments recorded in `deployments/`, e.g. to look up a contract's address.
+    Deployments(DeploymentsArgs),
+    /// Manage private keys stored in the OS keychain.
+    Key {
+        #[command(subcommand)]
+        command: Key,
+    },
     /// Generate c code bindings for a Stylus contract.
     Cgen { input: PathBuf, out_dir: PathBuf },
     /// Replay a transaction in gdb.
@@ -93,28 +188,151 @@ enum Apis {
 
 #[derive(Args, Clone, Debug)]
 struct CommonConfig {
-    /// Arbitrum RPC endpoint.
-    #[arg(short, long)]
-    endpoint: String,
-    /// Whether to print debug info.
+    /// Arbitrum RPC endpoint, over HTTP, websockets for `ws://` and `wss://` URLs, or IPC
+    /// for a local node's socket path like `/path/to/node.ipc`. Receipts are awaited through
+    /// subscriptions on websocket endpoints. Repeat it, or give a comma-separated list, to
+    /// fail over to the next endpoint when one can't be reached.
+    #[arg(
+        short,
+        long,
+        env = "PHAROS_RPC_URL",
+        value_delimiter = ',',
+        required = true
+    )]
+    endpoint: Vec<String>,
+    /// Times to retry an RPC request that fails without an answer from any endpoint, such
+    /// as on a dropped connection. Sending a tx is never retried.
+    #[arg(long, default_value_t = 3)]
+    rpc_retries: u32,
+    /// Milliseconds to wait before the first RPC retry, doubling with each retry after.
+    #[arg(long, default_value_t = 500)]
+    rpc_backoff_ms: u64,
+    /// Extra `KEY:VALUE` header to send to HTTP RPC endpoints, e.g. an API key. Repeat for
+    /// several headers.
+    #[arg(long, value_parser = sys::parse_header)]
+    rpc_header: Vec<(String, String)>,
+    /// Token to authenticate to the RPC endpoints with, as `Authorization: Bearer <token>`.
+    #[arg(long, env = "PHAROS_RPC_BEARER_TOKEN", hide_env_values = true)]
+    rpc_bearer_token: Option<String>,
+    /// HTTP or SOCKS proxy to send HTTP requests through, e.g. `socks5://localhost:1080`.
+    /// Defaults to the `HTTPS_PROXY` environment variable. Websocket endpoints can't go
+    /// through a proxy, so they're rejected with `--proxy` and connect directly otherwise.
+    #[arg(long)]
+    proxy: Option<String>,
+    /// Seconds to wait on an HTTP RPC request before giving up on it.
+    #[arg(long)]
+    rpc_timeout: Option<u64>,
+    /// Milliseconds between polls for transaction receipts.
+    #[arg(long, default_value_t = 250)]
+    poll_interval: u64,
+    /// Seconds to wait for a sent transaction to be confirmed. The transaction may still be
+    /// included after this, so check on it before sending it again.
+    #[arg(long)]
+    tx_timeout: Option<u64>,
+    /// Websocket RPC endpoint used to await transaction receipts through block
+    /// subscriptions instead of HTTP polling. It's sent the same `--rpc-bearer-token` as
+    /// `--endpoint`.
     #[arg(long)]
+    endpoint_ws: Option<String>,
+    /// Chain id the endpoint must report, guarding against a stale or misconfigured URL
+    /// sending transactions to the wrong chain.
+    #[arg(long)]
+    expected_chain_id: Option<u64>,
+    /// Whether to print debug info.
+    #[arg(long, env = "PHAROS_VERBOSE")]
     verbose: bool,
+    /// Don't show progress indicators.
+    #[arg(long)]
+    quiet: bool,
+    /// Print the result as a single JSON document instead of the human-readable output,
+    /// for scripts and CI.
+    #[arg(long)]
+    json: bool,
     /// The path to source files to include in the project hash, which
     /// is included in the contract deployment init code transaction
     /// to be used for verification of deployment integrity.
     /// If not provided, all .rs files and Cargo.toml and Cargo.lock files
     /// in project's directory tree are included.
-    #[arg(long)]
+    #[arg(
+        long,
+        env = "PHAROS_SOURCE_FILES_FOR_PROJECT_HASH",
+        value_delimiter = ','
+    )]
     source_files_for_project_hash: Vec<String>,
-    #[arg(long)]
+    #[arg(long, env = "PHAROS_MAX_FEE_PER_GAS_GWEI")]
     /// Optional max fee per gas in gwei units.
     max_fee_per_gas_gwei: Option<u128>,
-    /// Specifies the features to use when building the Stylus binary.
+    /// Max priority fee (tip) per gas in gwei, paid along with `--max-fee-per-gas-gwei`
+    /// instead of the network's suggestion.
+    #[arg(long, requires = "max_fee_per_gas_gwei")]
+    max_priority_fee_per_gas_gwei: Option<u128>,
+    /// Pay this many percent over the network's suggested gas price, instead of an
+    /// absolute max fee per gas.
+    #[arg(long, conflicts_with = "max_fee_per_gas_gwei")]
+    gas_price_bump_percent: Option<u64>,
+    /// When no fee is specified, the max fee per gas is the latest base fee times this
+    /// multiplier, plus the priority fee, leaving headroom for the base fee to rise.
+    #[arg(long, default_value_t = 2.0)]
+    fee_multiplier: f64,
+    /// Refuse to send transactions when the gas price exceeds this many gwei.
     #[arg(long)]
+    gas_price_cap_gwei: Option<u128>,
+    /// Specifies the features to use when building the Stylus binary.
+    #[arg(long, env = "PHAROS_FEATURES")]
     features: Option<String>,
+    /// Abort the whole command if it takes longer than this many seconds.
+    #[arg(long)]
+    timeout_secs: Option<u64>,
+    /// Cargo profile to build the WASM with, e.g. a custom `[profile.wasm]` (defaults to release).
+    #[arg(long)]
+    profile: Option<String>,
+    /// Extra flags appended to `RUSTFLAGS` for the WASM build only. Note that `RUSTFLAGS`
+    /// replaces any `rustflags` in `.cargo/config.toml`, so repeat flags like
+    /// `-C link-arg=-zstack-size=...` from there.
+    #[arg(long, allow_hyphen_values = true)]
+    rustflags: Option<String>,
+    /// Fail the WASM build if the contract's code emits any warnings. Like `--rustflags`,
+    /// this sets `RUSTFLAGS` for the build.
+    #[arg(long)]
+    fail_on_warnings: bool,
+    /// Binary target to build and use, for crates that produce several WASMs. The library
+    /// is built when unset.
+    #[arg(long)]
+    bin: Option<String>,
+    /// The compression scheme applied to the WASM before deployment.
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = Compression::Brotli,
+        default_value_if("no_compress", "true", "none")
+    )]
+    compression: Compression,
+    /// Deploy the WASM uncompressed, to tell compression issues apart from code issues.
+    /// Shorthand for `--compression none`, and only accepted by some nodes.
+    #[arg(long, conflicts_with = "compression")]
+    no_compress: bool,
+    #[command(flatten)]
+    native_token: NativeTokenOpts,
 }
 
 #[derive(Subcommand, Clone, Debug)]
+enum Key {
+    /// Prompt for a private key and store it in the OS keychain under a name.
+    Store {
+        /// Name to pass to `--keychain-key` to sign with the key.
+        name: String,
+    },
+    /// List the names of the keys stored in the OS keychain.
+    List,
+    /// Remove a key from the OS keychain.
+    Remove {
+        /// Name the key was stored under.
+        name: String,
+    },
+}
+
+#[derive(Subcommand, Clone, Debug)]
+#[allow(clippy::large_enum_variant)]
 enum Cache {
     /// Places a bid on a Stylus contract to cache it in the Arbitrum chain's wasm cache manager.
     #[command(visible_alias = "b")]
@@ -190,11 +408,68 @@ pub struct CheckConfig {
     #[command(flatten)]
     data_fee: DataFeeOpts,
     /// The WASM to check (defaults to any found in the current directory).
-    #[arg(long)]
+    #[arg(long, env = "PHAROS_WASM_FILE")]
     wasm_file: Option<PathBuf>,
     /// Where to deploy and activate the contract (defaults to a random address).
     #[arg(long)]
     contract_address: Option<H160>,
+    /// Run `wasm-opt -Oz` over the built WASM before compressing it. Requires binaryen.
+    #[arg(long)]
+    optimize: bool,
+    /// Don't embed the project hash section, saving a few bytes. Contracts built this way
+    /// can't be checked with `cargo stylus verify`.
+    #[arg(long)]
+    no_project_hash: bool,
+    /// Override the limit on the size of the deployed code, for networks that differ. It
+    /// applies to the compressed code, or to the WASM itself with `--no-compress`.
+    #[arg(long)]
+    max_code_size_bytes: Option<u64>,
+    /// Override the limit on the number of functions the WASM may define.
+    #[arg(long)]
+    max_functions: Option<u32>,
+    /// Append the contract's sizes to this JSON-lines file on each run.
+    #[arg(long)]
+    size_log: Option<PathBuf>,
+    /// Print the size change between the last two entries of the size log.
+    #[arg(long, requires = "size_log")]
+    size_diff: bool,
+    /// Write the check results to this file as SARIF, for code-scanning UIs to show as
+    /// annotations. It's written whether or not the checks pass.
+    #[arg(long)]
+    sarif: Option<PathBuf>,
+    /// Strip debug and other non-essential custom sections from the WASM before compressing it.
+    /// The project hash section is always kept. Pass `--strip=false` to keep them.
+    #[arg(
+        long,
+        default_value_t = true,
+        num_args = 0..=1,
+        default_missing_value = "true",
+        action = ArgAction::Set
+    )]
+    strip: bool,
+    /// Install the project's toolchain through rustup if it is missing, instead of failing.
+    #[arg(long)]
+    auto_install_toolchain: bool,
+    /// Print only the compressed size in bytes, as a bare integer for scripts, and nothing
+    /// else. Skips the ABI export.
+    #[arg(long, conflicts_with = "json")]
+    size_only: bool,
+    /// Write the hex-encoded deployment calldata to this file instead of printing it.
+    #[arg(long)]
+    calldata_out: Option<PathBuf>,
+    /// Write the processed WASM, before compression, to this file.
+    #[arg(long)]
+    wasm_out: Option<PathBuf>,
+    /// Write the compressed WASM, the code the prelude deploys, to this file.
+    #[arg(long)]
+    compressed_out: Option<PathBuf>,
+    /// Write the EVM deployment prelude, version byte included, to this file. It is empty
+    /// with `--raw-code`.
+    #[arg(long)]
+    prelude_out: Option<PathBuf>,
+    /// Deploy the code as-is, without the CODECOPY prelude, for targets that wrap it themselves.
+    #[arg(long)]
+    raw_code: bool,
 }
 
 #[derive(Args, Clone, Debug)]
@@ -207,16 +482,44 @@ struct DeployConfig {
     /// Only perform gas estimation.
     #[arg(long)]
     estimate_gas: bool,
+    /// Only estimate, printing the data fee, constructor value and gas cost as one total.
+    #[arg(long)]
+    estimate_total: bool,
     /// Cargo stylus version when deploying reproducibly to downloads the corresponding cargo-stylus-base Docker image.
     /// If not set, uses the default version of the local cargo stylus binary.
     #[arg(long)]
     cargo_stylus_version: Option<String>,
-    /// The salt passed to the stylus deployer.
+    /// The salt passed to the stylus deployer, or to the `--factory`.
     #[arg(long, default_value_t = B256::ZERO)]
     experimental_deployer_salt: B256,
-    /// The constructor arguments.
+    /// Deploy deterministically with CREATE2 under this salt, through the
+    /// `--create2-deployer`. The address depends only on the deployer, salt and init code,
+    /// so it is known ahead of time and the same across chains.
+    #[arg(long, conflicts_with = "factory")]
+    salt: Option<H256>,
+    /// The CREATE2 deployer proxy used with `--salt`.
+    #[arg(long, default_value = constants::CREATE2_DEPLOYER_ADDRESS, requires = "salt")]
+    create2_deployer: H160,
+    /// Deploy with CREATE3 under the `--salt`, through the `--create3-factory`. The address
+    /// depends only on the sender and salt, so it stays the same as the contract changes.
+    #[arg(long, requires = "salt")]
+    create3: bool,
+    /// The CREATE3 factory used with `--create3`. It must already be deployed on the target
+    /// chain; cargo stylus checks for its code but doesn't deploy it.
+    #[arg(long, default_value = constants::CREATE3_FACTORY_ADDRESS, requires = "create3")]
+    create3_factory: H160,
+    /// Deploy through this factory contract instead of a creation tx. The factory is called
+    /// with the deployment calldata and salt, and must return the new contract's address.
+    #[arg(long)]
+    factory: Option<H160>,
+    /// Selector of the factory's deploy method (defaults to `deploy(bytes,bytes32)`).
+    #[arg(long, value_parser = parse_selector, requires = "factory")]
+    factory_selector: Option<[u8; 4]>,
+    /// The constructor arguments, ABI-encoded against the contract's constructor and appended
+    /// to the deployment calldata.
     #[arg(
         long,
+        visible_alias = "constructor-args",
         num_args(0..),
         value_name = "ARGS",
         allow_hyphen_values = true,
@@ -225,6 +528,131 @@ struct DeployConfig {
     /// The amount of Ether sent to the contract through the constructor.
     #[arg(long, value_parser = parse_ether, default_value = "0")]
     experimental_constructor_value: U256,
+    /// Run the deployment in an in-process EVM first, stopping if it would fail.
+    #[arg(long)]
+    simulate: bool,
+    /// Only simulate the deployment and estimate its gas, without sending it.
+    #[arg(long)]
+    simulate_only: bool,
+    /// Retry the deployment once with 1.5x the gas limit if it runs out of gas.
+    #[arg(long)]
+    auto_bump_gas: bool,
+    /// How to report contract sizes and the deployment estimate.
+    #[arg(long, value_enum, default_value_t = ReportFormat::Lines, conflicts_with = "json")]
+    report: ReportFormat,
+    /// Block state to read the sender's nonce from.
+    #[arg(long, value_enum, default_value_t = BlockTag::Pending)]
+    nonce_block: BlockTag,
+    /// Block state to estimate the deployment gas against (defaults to the node's choice).
+    #[arg(long, value_enum)]
+    estimate_block: Option<BlockTag>,
+    /// Hex-encoded calldata (selector and arguments) to send to the contract once deployed,
+    /// e.g. to set an owner or admin.
+    #[arg(long)]
+    post_deploy_call: Option<ethers::types::Bytes>,
+    /// Hex-encoded calldata to send to the contract once deployed, after any
+    /// `--post-deploy-call`, e.g. to initialize it and then transfer ownership. Repeat to
+    /// send several calls in order. Sending stops at the first call that fails.
+    #[arg(long)]
+    then: Vec<ethers::types::Bytes>,
+    /// A 4-byte function selector to call on the deployed contract to confirm it executes.
+    #[arg(long, value_parser = parse_selector)]
+    smoke_test_selector: Option<[u8; 4]>,
+    /// Skip building and submitting, and instead await the receipt of a deployment tx that
+    /// was already sent, e.g. when a previous run was interrupted.
+    #[arg(long)]
+    resume_tx: Option<H256>,
+    /// An EIP-2930 access list to attach to the deployment tx, as JSON,
+    /// e.g. `[{"address":"0x…","storageKeys":["0x…"]}]`.
+    #[arg(long, value_parser = parse_access_list, conflicts_with = "auto_access_list")]
+    access_list: Option<AccessList>,
+    /// Derive the deployment tx's access list from the node via `eth_createAccessList`.
+    #[arg(long)]
+    auto_access_list: bool,
+    /// Blocks the deployment, and with it the activation, must hold for before success
+    /// is reported. Raise this on reorg-prone networks.
+    #[arg(long, default_value_t = 1)]
+    activation_confirmations: u64,
+    /// Refuse to deploy from a git working tree with uncommitted changes, so the deployed
+    /// project hash corresponds to a committed state.
+    #[arg(long)]
+    require_clean_git: bool,
+    /// Don't record the deployment in `deployments/<chain id>.json`.
+    #[arg(long)]
+    no_deployments_file: bool,
+    /// After deploying, print the logs the new contract emits until interrupted with Ctrl-C.
+    /// Logs are streamed over `--endpoint-ws` when set, and polled for otherwise.
+    #[arg(long, conflicts_with = "json")]
+    watch: bool,
+    /// Break the estimated deployment gas down into the intrinsic costs of the tx, such as
+    /// its calldata, and what remains for executing the deployment.
+    #[arg(long, conflicts_with = "json")]
+    gas_report: bool,
+    /// Keccak hash the deployed code must have, e.g. to check a prebuilt `--wasm-file` is the
+    /// intended build. The deployment stops before sending anything if the hash differs.
+    #[arg(long)]
+    expect_codehash: Option<H256>,
+    /// Build and sign the deployment tx without touching the network, writing it to `--out`
+    /// as hex-encoded raw RLP to submit later with `cargo stylus broadcast`. The nonce, gas
+    /// limit, chain id, `--max-fee-per-gas-gwei` and `--max-priority-fee-per-gas-gwei` must be
+    /// given, as there's no network to suggest them.
+    #[arg(
+        long,
+        requires_all = [
+            "out",
+            "nonce",
+            "gas_limit",
+            "chain_id",
+            "max_fee_per_gas_gwei",
+            "max_priority_fee_per_gas_gwei",
+        ],
+        conflicts_with_all = ["factory", "salt", "resume_tx"]
+    )]
+    offline: bool,
+    /// Where `--offline` writes the signed deployment tx.
+    #[arg(long, requires = "offline")]
+    out: Option<PathBuf>,
+    /// Nonce of the sender to sign the `--offline` deployment tx with.
+    #[arg(long, requires = "offline")]
+    nonce: Option<u64>,
+    /// Gas limit of the `--offline` deployment tx.
+    #[arg(long, requires = "offline")]
+    gas_limit: Option<u64>,
+    /// Chain id to sign the `--offline` deployment tx for.
+    #[arg(long, requires = "offline")]
+    chain_id: Option<u64>,
+    /// Propose the deployment to this Safe multisig instead of sending it, for its owners to
+    /// approve. The wallet must be an owner of the Safe.
+    #[arg(
+        long,
+        requires = "safe_tx_service",
+        conflicts_with_all = ["factory", "salt", "offline"]
+    )]
+    safe: Option<H160>,
+    /// URL of the Safe transaction service to propose `--safe` deployments to.
+    #[arg(long, requires = "safe")]
+    safe_tx_service: Option<String>,
+    /// The `CreateCall` library a `--safe` delegatecalls to deploy the contract.
+    #[arg(long, default_value = constants::SAFE_CREATE_CALL_ADDRESS, requires = "safe")]
+    safe_create_call: H160,
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct DiffConfig {
+    #[command(flatten)]
+    check_config: CheckConfig,
+    /// Address of the deployed contract to compare against.
+    #[arg(long)]
+    address: H160,
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct AnalyzeConfig {
+    #[command(flatten)]
+    check_config: CheckConfig,
+    /// Fail if any warnings are found.
+    #[arg(long)]
+    deny: bool,
 }
 
 #[derive(Args, Clone, Debug)]
@@ -275,6 +703,68 @@ struct TraceArgs {
     use_native_tracer: bool,
 }
 
+#[derive(Args, Clone, Debug)]
+struct BroadcastArgs {
+    /// File containing the hex-encoded raw signed tx.
+    file: PathBuf,
+    /// RPC endpoint.
+    #[arg(short, long, env = "PHAROS_RPC_URL")]
+    endpoint: String,
+    /// Websocket RPC endpoint used to await the receipt through block subscriptions
+    /// instead of HTTP polling.
+    #[arg(long)]
+    endpoint_ws: Option<String>,
+    /// Token to authenticate to the RPC endpoints with, as `Authorization: Bearer <token>`.
+    #[arg(long, env = "PHAROS_RPC_BEARER_TOKEN", hide_env_values = true)]
+    rpc_bearer_token: Option<String>,
+    /// Whether to print debug info.
+    #[arg(long)]
+    verbose: bool,
+}
+
+#[derive(Args, Clone, Debug)]
+struct PredictAddressArgs {
+    /// RPC endpoint, used to look up the deployer's nonce when neither `--nonce` nor
+    /// `--salt` is given.
+    #[arg(short, long)]
+    endpoint: Option<String>,
+    /// The deploying account, or the factory contract when using `--salt`.
+    #[arg(long)]
+    from: H160,
+    /// Nonce of the CREATE deployment tx.
+    #[arg(long, conflicts_with = "salt")]
+    nonce: Option<u64>,
+    /// Block state to read the deployer's nonce from, as `deploy --nonce-block` does.
+    #[arg(long, value_enum, default_value_t = BlockTag::Pending, conflicts_with = "nonce")]
+    nonce_block: BlockTag,
+    /// Salt of a CREATE2 deployment.
+    #[arg(long, requires = "init_code")]
+    salt: Option<H256>,
+    /// File holding the hex-encoded deployment calldata of a CREATE2 deployment, as
+    /// written by `--calldata-out`.
+    #[arg(long, requires = "salt")]
+    init_code: Option<PathBuf>,
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct DeploymentsArgs {
+    /// Only list deployments on this chain.
+    #[arg(long)]
+    chain_id: Option<u64>,
+    /// Only list deployments of this contract, by crate name or prebuilt WASM file name.
+    #[arg(long)]
+    contract: Option<String>,
+    /// Only list the latest deployment of each contract on each chain.
+    #[arg(long)]
+    latest: bool,
+    /// Print the deployments as a JSON array.
+    #[arg(long)]
+    json: bool,
+    /// Directory the deployments are recorded in.
+    #[arg(long, default_value = constants::DEPLOYMENTS_DIR)]
+    dir: PathBuf,
+}
+
 #[derive(Args, Clone, Debug)]
 pub struct SimulateArgs {
     /// RPC endpoint.
@@ -322,20 +812,96 @@ struct DataFeeOpts {
 }
 
 #[derive(Clone, Debug, Args)]
-#[clap(group(ArgGroup::new("key").required(true).args(&["private_key_path", "private_key", "keystore_path"])))]
+struct NativeTokenOpts {
+    /// Symbol of the native token that gas is paid in, for networks not priced in ETH.
+    #[arg(long, default_value = "ETH")]
+    native_symbol: String,
+    /// Decimals of the native token, used to format costs.
+    #[arg(long, default_value_t = 18)]
+    native_decimals: u32,
+}
+
+#[derive(Clone, Debug, Args)]
+#[clap(group(ArgGroup::new("key").required(true).args(&["private_key_path", "private_key", "keychain_key", "keystore_path", "mnemonic", "mnemonic_path", "ledger", "trezor", "aws_kms_key_id", "remote_signer_url"])))]
+#[clap(group(ArgGroup::new("hd_wallet").args(&["mnemonic", "mnemonic_path", "ledger", "trezor"])))]
 struct AuthOpts {
     /// File path to a text file containing a hex-encoded private key.
     #[arg(long)]
     private_key_path: Option<PathBuf>,
-    /// Private key as a hex string. Warning: this exposes your key to shell history.
-    #[arg(long)]
+    /// Private key as a hex string. Warning: this exposes your key to shell history, so
+    /// prefer setting it in the environment or a dotenv file.
+    #[arg(long, env = "PHAROS_PRIVATE_KEY", hide_env_values = true)]
     private_key: Option<String>,
+    /// Name of a private key stored in the OS keychain with `cargo stylus key store`.
+    #[arg(long)]
+    keychain_key: Option<String>,
     /// Path to an Ethereum wallet keystore file (e.g. clef).
     #[arg(long)]
     keystore_path: Option<String>,
-    /// Keystore password file.
-    #[arg(long)]
+    /// Keystore password file. The password is prompted for when this isn't given.
+    #[arg(long, requires = "keystore_path")]
     keystore_password_path: Option<PathBuf>,
+    /// BIP-39 mnemonic to derive the account from. Warning: this exposes the phrase to shell
+    /// history, so prefer `--mnemonic-path`.
+    #[arg(long)]
+    mnemonic: Option<String>,
+    /// File path to a text file containing a BIP-39 mnemonic to derive the account from.
+    #[arg(long)]
+    mnemonic_path: Option<PathBuf>,
+    /// Sign with a Ledger hardware wallet.
+    #[arg(long)]
+    ledger: bool,
+    /// Sign with a Trezor hardware wallet.
+    #[arg(long)]
+    trezor: bool,
+    /// HD derivation path of the mnemonic or hardware wallet account, overriding
+    /// `--account-index`.
+    #[arg(long, requires = "hd_wallet")]
+    hd_path: Option<String>,
+    /// Index of the mnemonic or hardware wallet account, on the standard Ethereum path for
+    /// mnemonics and the Ledger Live or Trezor Live path for devices.
+    #[arg(long, default_value_t = 0, requires = "hd_wallet")]
+    account_index: u32,
+    /// Sign with this AWS KMS key, given by its id or ARN. The key must be an
+    /// `ECC_SECG_P256K1` signing key.
+    #[arg(long)]
+    aws_kms_key_id: Option<String>,
+    /// AWS region of the KMS key (defaults to `AWS_REGION`, or `us-east-1`).
+    #[arg(long, requires = "aws_kms_key_id")]
+    aws_region: Option<String>,
+    /// Named AWS profile to load credentials from (defaults to the environment, then the
+    /// default profile).
+    #[arg(long, requires = "aws_kms_key_id")]
+    aws_profile: Option<String>,
+    /// URL of a Web3Signer-compatible remote signer to send transactions to for signing,
+    /// keeping keys in its custody.
+    #[arg(long)]
+    remote_signer_url: Option<String>,
+    /// Account of the remote signer to sign with (defaults to its first account).
+    #[arg(long, requires = "remote_signer_url")]
+    remote_signer_address: Option<H160>,
+}
+
+impl CommonConfig {
+    /// Connects to the RPC endpoints as configured.
+    async fn new_provider(&self) -> Result<Provider<sys::RpcClient>> {
+        sys::new_failover_provider(&self.endpoint, &self.rpc_options()).await
+    }
+
+    /// How to connect to the RPC endpoints, including the websocket one.
+    fn rpc_options(&self) -> sys::RpcOptions {
+        sys::RpcOptions {
+            retry: sys::RetryPolicy {
+                retries: self.rpc_retries,
+                backoff: Duration::from_millis(self.rpc_backoff_ms),
+            },
+            headers: self.rpc_header.clone(),
+            bearer_token: self.rpc_bearer_token.clone(),
+            proxy: self.proxy.clone(),
+            timeout: self.rpc_timeout.map(Duration::from_secs),
+            poll_interval: Some(Duration::from_millis(self.poll_interval)),
+        }
+    }
 }
 
 impl fmt::Display for CommonConfig {
@@ -350,8 +916,8 @@ impl fmt::Display for CommonConfig {
         }
         write!(
             f,
-            "--endpoint={} {} {} {}",
-            self.endpoint,
+            "--endpoint={} {} {} {} --compression={}",
+            self.endpoint.join(","),
             match self.verbose {
                 true => "--verbose",
                 false => "",
@@ -360,7 +926,8 @@ impl fmt::Display for CommonConfig {
             match &self.max_fee_per_gas_gwei {
                 Some(fee) => format!("--max-fee-per-gas-gwei {}", fee),
                 None => "".to_string(),
-            }
+            },
+            self.compression,
         )
     }
 }
@@ -476,6 +1043,8 @@ fn main() -> Result<()> {
     }
 
     let args: Vec<String> = std::env::args().skip(1).collect();
+    load_env_file(&args)?;
+    stylus_toml::load_stylus_toml(args.iter().any(|arg| arg == "--verbose"))?;
     let opts = Opts::parse_from(args);
     // use the current thread for replay.
     let mut runtime = match opts.command {
@@ -483,7 +1052,47 @@ fn main() -> Result<()> {
         _ => Builder::new_multi_thread(),
     };
     let runtime = runtime.enable_all().build()?;
-    runtime.block_on(main_impl(opts))
+    let result = runtime.block_on(main_impl(opts));
+    // don't wait on work abandoned after a --timeout-secs expiry.
+    runtime.shutdown_background();
+    result
+}
+
+/// Loads the dotenv file given by `--env-file`, or `.env` when present. This runs ahead of
+/// argument parsing so the variables can back flags, hence scanning the raw args.
+fn load_env_file(args: &[String]) -> Result<()> {
+    let explicit = args
+        .iter()
+        .enumerate()
+        .find_map(|(i, arg)| match arg.as_str() {
+            "--env-file" => args.get(i + 1).map(PathBuf::from),
+            _ => arg.strip_prefix("--env-file=").map(PathBuf::from),
+        });
+    let path = match explicit {
+        Some(path) => path,
+        None if Path::new(".env").is_file() => PathBuf::from(".env"),
+        None => return Ok(()),
+    };
+    let vars = dotenvy::from_path_iter(&path)
+        .wrap_err_with(|| eyre!("failed to read env file {}", path.to_string_lossy()))?;
+    let mut loaded = vec![];
+    for var in vars {
+        let (key, value) =
+            var.wrap_err_with(|| eyre!("failed to parse env file {}", path.to_string_lossy()))?;
+        if env::var_os(&key).is_none() {
+            env::set_var(&key, value);
+            loaded.push(key);
+        }
+    }
+    // Only the names are printed, never the values, which may be secrets.
+    if args.iter().any(|arg| arg == "--verbose") {
+        greyln!(
+            "loaded {} from {}",
+            loaded.join(", "),
+            path.to_string_lossy().lavender()
+        );
+    }
+    Ok(())
 }
 
 // Checks if a cargo stylus extension is an old, deprecated extension which is no longer
@@ -504,28 +1113,114 @@ async fn main_impl(args: Opts) -> Result<()> {
     }
 
     match args.command {
-        Apis::New { name } => {
-            run!(new::new(&name), "failed to open new project");
+        Apis::New { name, proxy } => {
+            run!(
+                new::new(&name, proxy.as_deref()),
+                "failed to open new project"
+            );
         }
-        Apis::Init {} => {
-            run!(new::init(), "failed to initialize project");
+        Apis::Init { proxy } => {
+            run!(new::init(proxy.as_deref()), "failed to initialize project");
         }
-        Apis::ExportAbi { json, output } => {
-            run!(export_abi::export_abi(output, json), "failed to export abi");
+        Apis::ListTemplates => new::list_templates(),
+        Apis::Doctor => {
+            run!(doctor::doctor(), "environment is not ready to build");
+        }
+        Apis::ExportAbi {
+            json,
+            output,
+            abi_public_only,
+        } => {
+            run!(
+                export_abi::export_abi(output, json, abi_public_only),
+                "failed to export abi"
+            );
         }
         Apis::Simulate(args) => {
             run!(simulate(args).await, "failed to simulate transaction");
         }
+        Apis::Ping { endpoint } => {
+            run!(ping(&endpoint).await, "failed to ping endpoint");
+        }
+        Apis::Inspect {
+            endpoint,
+            deployment_tx,
+        } => {
+            run!(
+                inspect::inspect(&endpoint, deployment_tx).await,
+                "failed to inspect deployment"
+            );
+        }
+        Apis::Hash { wasm_file } => {
+            run!(hash(&wasm_file), "failed to read project hash");
+        }
+        Apis::EmbedHash {
+            wasm_file,
+            hash,
+            output,
+        } => {
+            run!(
+                embed_hash(&wasm_file, hash, &output),
+                "failed to embed project hash"
+            );
+        }
+        Apis::PredictAddress(args) => {
+            run!(predict_address(args).await, "failed to predict address");
+        }
+        Apis::Broadcast(args) => {
+            run!(broadcast(args).await, "failed to broadcast tx");
+        }
+        Apis::Deployments(args) => {
+            run!(
+                deploy::deployments::list_deployments(&args),
+                "failed to list deployments"
+            );
+        }
+        Apis::Key { command } => match command {
+            Key::Store { name } => run!(keychain::store(&name), "failed to store key"),
+            Key::List => run!(keychain::list(), "failed to list keys"),
+            Key::Remove { name } => run!(keychain::remove(&name), "failed to remove key"),
+        },
         Apis::Cgen { input, out_dir } => {
             run!(gen::c_gen(&input, &out_dir), "failed to generate c code");
         }
         Apis::Trace(args) => run!(trace(args).await, "failed to trace tx"),
         Apis::Replay(args) => run!(replay(args).await, "failed to replay tx"),
         Apis::Check(config) => {
-            run!(check::check(&config).await, "stylus checks failed");
+            let timeout = config.common_cfg.timeout_secs;
+            let size_only = config.size_only;
+            let json = config.common_cfg.json;
+            if json {
+                util::color::silence();
+            }
+            let check = async move { check::check(&config).await };
+            let contract = run!(with_timeout(timeout, check).await, "stylus checks failed");
+            if size_only {
+                println!("{}", contract.compressed_size());
+            }
+            if json {
+                print_json(contract.report())?;
+            }
         }
         Apis::Deploy(config) => {
-            run!(deploy::deploy(config).await, "stylus deploy failed");
+            let timeout = config.check_config.common_cfg.timeout_secs;
+            let json = config.check_config.common_cfg.json;
+            if json {
+                util::color::silence();
+            }
+            let report = run!(
+                with_timeout(timeout, deploy::deploy(config)).await,
+                "stylus deploy failed"
+            );
+            if json {
+                print_json(&report)?;
+            }
+        }
+        Apis::Diff(config) => {
+            run!(diff::diff(config).await, "failed to diff contract");
+        }
+        Apis::Analyze(config) => {
+            run!(analyze::analyze(config).await, "failed to analyze contract");
         }
         Apis::Verify(config) => {
             run!(verify::verify(config).await, "failed to verify");
@@ -534,20 +1229,157 @@ async fn main_impl(args: Opts) -> Result<()> {
     Ok(())
 }
 
+/// Prints a report as pretty JSON on stdout.
+fn print_json<T: serde::Serialize>(report: &T) -> Result<()> {
+    let json = serde_json::to_string_pretty(report).wrap_err("failed to serialize report")?;
+    println!("{json}");
+    Ok(())
+}
+
+/// Bounds the wall-clock time of a command when a timeout is configured.
+async fn with_timeout<T: Send + 'static>(
+    secs: Option<u64>,
+    fut: impl Future<Output = Result<T>> + Send + 'static,
+) -> Result<T> {
+    let Some(secs) = secs else {
+        return fut.await;
+    };
+    // Spawn the work so the timer still fires while it blocks on a build or RPC call.
+    let task = tokio::spawn(fut);
+    match tokio::time::timeout(Duration::from_secs(secs), task).await {
+        Ok(result) => result?,
+        Err(_) => bail!("operation exceeded {secs}s timeout"),
+    }
+}
+
 async fn trace(args: TraceArgs) -> Result<()> {
-    let provider = sys::new_provider(&args.endpoint)?;
+    let provider = sys::new_provider(&args.endpoint).await?;
     let trace = Trace::new(provider, args.tx, args.use_native_tracer).await?;
     println!("{}", trace.json);
     Ok(())
 }
 
 async fn simulate(args: SimulateArgs) -> Result<()> {
-    let provider = sys::new_provider(&args.endpoint)?;
+    let provider = sys::new_provider(&args.endpoint).await?;
     let trace = Trace::simulate(provider, &args).await?;
     println!("{}", trace.json);
     Ok(())
 }
 
+async fn ping(endpoint: &str) -> Result<()> {
+    let provider = sys::new_provider(endpoint).await?;
+    let chain_id = provider.get_chainid().await?;
+    let Some(block) = provider.get_block(BlockNumber::Latest).await? else {
+        bail!("endpoint returned no latest block");
+    };
+    let gas_price = provider.get_gas_price().await?;
+    let base_fee = match block.base_fee_per_gas {
+        Some(fee) => format!("{} gwei", format_units(fee, "gwei")?),
+        None => "none".to_string(),
+    };
+    let rows = [
+        ("chain id", chain_id.to_string()),
+        ("latest block", block.number.unwrap_or_default().to_string()),
+        (
+            "gas price",
+            format!("{} gwei", format_units(gas_price, "gwei")?),
+        ),
+        ("base fee", base_fee),
+    ];
+    print!("{}", util::text::format_table(&rows));
+    Ok(())
+}
+
+fn hash(wasm_file: &Path) -> Result<()> {
+    let wasm = std::fs::read(wasm_file)
+        .wrap_err_with(|| eyre!("failed to read {}", wasm_file.to_string_lossy()))?;
+    match compression::read_project_hash(&wasm)? {
+        Some(hash) => println!("{}", hex::encode(hash)),
+        None => println!("none"),
+    }
+    Ok(())
+}
+
+fn embed_hash(wasm_file: &Path, hash: B256, output: &Path) -> Result<()> {
+    let wasm = std::fs::read(wasm_file)
+        .wrap_err_with(|| eyre!("failed to read {}", wasm_file.to_string_lossy()))?;
+    let wasm = compression::embed_project_hash(&wasm, hash.0)?;
+    std::fs::write(output, wasm)
+        .wrap_err_with(|| eyre!("failed to write {}", output.to_string_lossy()))?;
+    Ok(())
+}
+
+async fn broadcast(args: BroadcastArgs) -> Result<()> {
+    let hex = std::fs::read_to_string(&args.file)
+        .wrap_err_with(|| eyre!("failed to read {}", args.file.to_string_lossy()))?;
+    let raw = util::text::decode0x(hex)?;
+    let (mut tx, signature) = TypedTransaction::decode_signed(&Rlp::new(&raw))
+        .map_err(|e| eyre!("invalid signed tx: {e}"))?;
+    // The sender isn't encoded, but is needed to replay the tx for a revert reason.
+    tx.set_from(signature.recover(tx.sighash())?);
+
+    let opts = sys::RpcOptions {
+        bearer_token: args.rpc_bearer_token.clone(),
+        ..Default::default()
+    };
+    let provider = sys::new_failover_provider(std::slice::from_ref(&args.endpoint), &opts).await?;
+    let watcher = match &args.endpoint_ws {
+        Some(url) => Some(sys::new_ws_provider(url, &opts).await?),
+        None if sys::is_ws_url(&args.endpoint) => {
+            Some(sys::new_ws_provider(&args.endpoint, &opts).await?)
+        }
+        None => None,
+    };
+    let mut timer = StepTimer::start(args.verbose);
+    let pending = provider.send_raw_transaction(raw.into()).await?;
+    greyln!("sent tx: {}", pending.tx_hash().debug_lavender());
+    timer.lap("tx submission");
+    let receipt = deploy::await_receipt(
+        "broadcast",
+        pending,
+        &tx,
+        &provider,
+        watcher.as_ref(),
+        None,
+        timer,
+    )
+    .await?;
+    match receipt.contract_address {
+        Some(address) => greyln!("deployed code at address: {}", address.debug_lavender()),
+        None => greyln!(
+            "confirmed tx: {}",
+            receipt.transaction_hash.debug_lavender()
+        ),
+    }
+    Ok(())
+}
+
+async fn predict_address(args: PredictAddressArgs) -> Result<()> {
+    let address = match (args.salt, &args.init_code) {
+        (Some(salt), Some(path)) => {
+            let hex = std::fs::read_to_string(path)
+                .wrap_err_with(|| eyre!("failed to read {}", path.to_string_lossy()))?;
+            let init_code = util::text::decode0x(hex)?;
+            ethers::utils::get_create2_address(args.from, salt, init_code)
+        }
+        _ => {
+            let nonce = match (args.nonce, &args.endpoint) {
+                (Some(nonce), _) => nonce.into(),
+                (None, Some(endpoint)) => {
+                    let provider = sys::new_provider(endpoint).await?;
+                    provider
+                        .get_transaction_count(args.from, Some(args.nonce_block.into()))
+                        .await?
+                }
+                (None, None) => bail!("pass --nonce or an --endpoint to look it up"),
+            };
+            ethers::utils::get_contract_address(args.from, nonce)
+        }
+    };
+    println!("{address:?}");
+    Ok(())
+}
+
 async fn replay(args: ReplayArgs) -> Result<()> {
     let macos = cfg!(target_os = "macos");
     if !args.child {
@@ -600,7 +1432,7 @@ async fn replay(args: ReplayArgs) -> Result<()> {
         bail!("failed to exec {cmd_name} {:?}", err);
     }
 
-    let provider = sys::new_provider(&args.trace.endpoint)?;
+    let provider = sys::new_provider(&args.trace.endpoint).await?;
     let trace = Trace::new(provider, args.trace.tx, args.trace.use_native_tracer).await?;
 
     build_shared_library(&args.trace.project, args.package, args.features)?;
@@ -684,3 +1516,14 @@ pub fn find_shared_library(project: &Path, extension: &str) -> Result<PathBuf> {
 fn parse_ether(s: &str) -> Result<U256> {
     Ok(ethers::utils::parse_ether(s)?)
 }
+
+fn parse_access_list(s: &str) -> Result<AccessList> {
+    serde_json::from_str(s).wrap_err("invalid access list")
+}
+
+fn parse_selector(s: &str) -> Result<[u8; 4]> {
+    let bytes = util::text::decode0x(s)?;
+    bytes
+        .try_into()
+        .map_err(|bytes: Vec<u8>| eyre!("selector must be 4 bytes, got {}", bytes.len()))
+}
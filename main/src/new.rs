@@ -1,52 +1,131 @@
 // Copyright 2023-2024, Offchain Labs, Inc.
 // For licensing, see https://github.com/OffchainLabs/cargo-stylus/blob/main/licenses/COPYRIGHT.md
 
-use crate::constants::GITHUB_TEMPLATE_REPO;
+use crate::constants::{GITHUB_TEMPLATE_REPO, GIT_TIMEOUT_SECS, TEMPLATES};
 use crate::util::{
     color::{Color, GREY},
-    sys,
+    spinner::with_spinner,
+    sys, text,
+};
+use eyre::{bail, eyre, Context, Result};
+use std::{
+    env,
+    ffi::OsStr,
+    fs,
+    io::Read,
+    path::Path,
+    process::{Command, Stdio},
+    time::Duration,
 };
-use eyre::{bail, Context, Result};
-use std::{env, fs, path::Path};
 
 /// Creates a new directory given the path and then initialize a stylus project.
-pub fn new(path: &Path) -> Result<()> {
+pub fn new(path: &Path, proxy: Option<&str>) -> Result<()> {
     fs::create_dir_all(path).wrap_err("failed to create project dir")?;
     env::set_current_dir(path).wrap_err("failed to set project dir")?;
-    init()
+    init(proxy)
 }
 
-/// Creates a new Stylus project in the current directory.
-pub fn init() -> Result<()> {
+/// Creates a new Stylus project in the current directory, cloning the template through
+/// the proxy if one is given.
+pub fn init(proxy: Option<&str>) -> Result<()> {
     let current_dir = env::current_dir().wrap_err("no current dir")?;
     let repo = GITHUB_TEMPLATE_REPO;
-    let output = sys::new_command("git")
-        .arg("clone")
-        .arg("--depth")
-        .arg("1")
-        .arg(repo)
-        .arg(".")
-        .output()
-        .wrap_err("git clone failed")?;
-
-    if !output.status.success() {
-        bail!("git clone command failed");
-    }
-
-    let output = sys::new_command("git")
-        .arg("remote")
-        .arg("remove")
-        .arg("origin")
-        .output()
-        .wrap_err("git remote remove failed")?;
 
-    if !output.status.success() {
-        bail!("git remote remove command failed");
+    // Cloning into "." and removing "origin" would act on the outer repo's state,
+    // so clone elsewhere and move the files in instead.
+    if inside_git_worktree() {
+        clone_without_git(repo, &current_dir, proxy)?;
+        println!(
+            "{GREY}initialized project in: {}",
+            current_dir.to_string_lossy().mint()
+        );
+        return Ok(());
     }
 
+    let clone = ["clone", "--quiet", "--depth", "1", repo, "."];
+    run_git("clone", clone, proxy)?;
+    run_git("remote remove", ["remote", "remove", "origin"], None)?;
+
     println!(
         "{GREY}initialized project in: {}",
         current_dir.to_string_lossy().mint()
     );
     Ok(())
 }
+
+/// Whether the current directory is inside an existing git worktree.
+fn inside_git_worktree() -> bool {
+    Command::new("git")
+        .arg("rev-parse")
+        .arg("--is-inside-work-tree")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map(|x| x.success())
+        .unwrap_or_default()
+}
+
+/// Clones a repo into a scratch dir and moves its files, minus `.git`, into `dest`.
+fn clone_without_git(repo: &str, dest: &Path, proxy: Option<&str>) -> Result<()> {
+    let scratch = tempfile::tempdir_in(dest).wrap_err("failed to create temp dir")?;
+    let mut args = ["clone", "--quiet", "--depth", "1", repo]
+        .map(OsStr::new)
+        .to_vec();
+    args.push(scratch.path().as_os_str());
+    run_git("clone", args, proxy)?;
+
+    fs::remove_dir_all(scratch.path().join(".git")).wrap_err("failed to remove .git")?;
+    for entry in fs::read_dir(scratch.path())? {
+        let entry = entry?;
+        let target = dest.join(entry.file_name());
+        if target.exists() {
+            bail!("{} already exists", target.to_string_lossy().red());
+        }
+        fs::rename(entry.path(), &target)
+            .wrap_err_with(|| eyre!("failed to move {}", target.to_string_lossy()))?;
+    }
+    Ok(())
+}
+
+/// Runs a git command behind a spinner, bailing if it stalls past the timeout, e.g. on a
+/// flaky network. Without a proxy, git falls back to its own config and `HTTPS_PROXY`.
+fn run_git<I, S>(name: &str, args: I, proxy: Option<&str>) -> Result<()>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    let mut command = Command::new("git");
+    if let Some(proxy) = proxy {
+        command.arg("-c").arg(format!("http.proxy={proxy}"));
+    }
+    let mut child = command
+        .args(args)
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .wrap_err_with(|| eyre!("git {name} failed"))?;
+    let timeout = Duration::from_secs(GIT_TIMEOUT_SECS);
+    let status = with_spinner(&format!("running git {name}"), false, || {
+        sys::wait_with_timeout(&mut child, timeout)
+    })?;
+    let Some(status) = status else {
+        bail!("git {name} timed out after {GIT_TIMEOUT_SECS}s — check your network");
+    };
+    if !status.success() {
+        let mut stderr = String::new();
+        if let Some(mut pipe) = child.stderr.take() {
+            let _ = pipe.read_to_string(&mut stderr);
+        }
+        bail!("git {name} command failed: {}", stderr.trim());
+    }
+    Ok(())
+}
+
+/// Prints the built-in project templates and their repos.
+pub fn list_templates() {
+    let rows: Vec<_> = TEMPLATES
+        .iter()
+        .map(|(name, repo)| (*name, repo.to_string()))
+        .collect();
+    print!("{}", text::format_table(&rows));
+}
@@ -0,0 +1,219 @@
+// Copyright 2023-2024, Offchain Labs, Inc.
+// For licensing, see https://github.com/OffchainLabs/cargo-stylus/blob/main/licenses/COPYRIGHT.md
+
+//! The EVM init code that wraps a compressed contract for deployment.
+
+use crate::compression::Compression;
+use ethers::types::U256;
+use eyre::{bail, Result};
+
+/// Largest code the deployment prelude will wrap, in bytes. This is far beyond what any
+/// network accepts, and only guards against producing init code for pathological inputs.
+pub const MAX_DEPLOYMENT_CODE_BYTES: usize = 16 * 1024 * 1024;
+
+/// Prepares an EVM bytecode prelude for contract creation.
+pub fn contract_deployment_calldata(code: &[u8], compression: Compression) -> Result<Vec<u8>> {
+    if code.len() > MAX_DEPLOYMENT_CODE_BYTES {
+        bail!(
+            "code of {} B is too large to deploy, the limit is {MAX_DEPLOYMENT_CODE_BYTES} B",
+            code.len()
+        );
+    }
+    let mut deploy = prelude(code.len(), compression);
+    deploy.extend(code);
+    Ok(deploy)
+}
+
+/// The init code that copies the `code_len` bytes following it into memory and returns them.
+fn prelude(code_len: usize, compression: Compression) -> Vec<u8> {
+    let mut len_bytes = [0u8; 32];
+    U256::from(code_len).to_big_endian(&mut len_bytes);
+    let mut deploy: Vec<u8> = vec![];
+    deploy.push(0x7f); // PUSH32
+    deploy.extend(len_bytes);
+    deploy.push(0x80); // DUP1
+    deploy.push(0x60); // PUSH1
+    let code_offset = deploy.len();
+    deploy.push(0x00); // prelude length, patched below
+    deploy.push(0x60); // PUSH1
+    deploy.push(0x00);
+    deploy.push(0x39); // CODECOPY
+    deploy.push(0x60); // PUSH1
+    deploy.push(0x00);
+    deploy.push(0xf3); // RETURN
+    deploy.push(compression.prelude_version()); // version
+
+    // The code is copied from right after the prelude, wherever that ends.
+    deploy[code_offset] = deploy
+        .len()
+        .try_into()
+        .expect("prelude longer than a PUSH1 offset");
+    deploy
+}
+
+/// Prepares the deployment calldata, which is the code itself when `raw` is set, for
+/// targets such as factories that wrap the code themselves.
+pub fn deployment_calldata(code: &[u8], compression: Compression, raw: bool) -> Result<Vec<u8>> {
+    match raw {
+        true => Ok(code.to_vec()),
+        false => contract_deployment_calldata(code, compression),
+    }
+}
+
+/// The length of the prelude, version byte included, that precedes the code.
+pub fn deployment_prelude_len() -> usize {
+    prelude(0, Compression::default()).len()
+}
+
+/// The length of the code following the prelude, read from its PUSH32, or `None` if the
+/// calldata doesn't start with a prelude. Constructor args may follow the code.
+fn prelude_code_len(calldata: &[u8]) -> Option<usize> {
+    let len = deployment_prelude_len();
+    if calldata.len() < len {
+        return None;
+    }
+    let code_len = U256::from_big_endian(&calldata[1..33]);
+    if code_len > U256::from(calldata.len() - len) {
+        return None;
+    }
+    let code_len = code_len.as_usize();
+    // Everything but the version byte is determined by the length of the code.
+    let expected = prelude(code_len, Compression::default());
+    (calldata[..len - 1] == expected[..len - 1]).then_some(code_len)
+}
+
+/// Whether the calldata starts with the prelude, rather than being raw code.
+pub fn has_deployment_prelude(calldata: &[u8]) -> bool {
+    prelude_code_len(calldata).is_some()
+}
+
+/// The prelude of the calldata, which is empty for raw code.
+pub fn extract_contract_evm_deployment_prelude(calldata: &[u8]) -> Vec<u8> {
+    match has_deployment_prelude(calldata) {
+        true => calldata[0..deployment_prelude_len()].to_vec(),
+        false => vec![],
+    }
+}
+
+/// Reads the compression scheme signaled by the prelude's version byte.
+pub fn extract_compression(calldata: &[u8]) -> Result<Compression> {
+    if !has_deployment_prelude(calldata) {
+        bail!("deployment calldata has no prelude to signal its compression");
+    }
+    Compression::from_prelude_version(calldata[deployment_prelude_len() - 1])
+}
+
+/// The code the calldata deploys, with the prelude and any constructor args removed if
+/// there is a prelude.
+pub fn extract_compressed_wasm(calldata: &[u8]) -> Vec<u8> {
+    match prelude_code_len(calldata) {
+        Some(code_len) => {
+            let start = deployment_prelude_len();
+            calldata[start..start + code_len].to_vec()
+        }
+        None => calldata.to_vec(),
+    }
+}
+
+/// The constructor args appended after the code, which raw code can't be told apart from.
+pub fn extract_constructor_args(calldata: &[u8]) -> Vec<u8> {
+    match prelude_code_len(calldata) {
+        Some(code_len) => calldata[deployment_prelude_len() + code_len..].to_vec(),
+        None => vec![],
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Runs init code through the handful of opcodes the prelude uses and
+    /// returns the runtime code it deploys.
+    fn run_init_code(init_code: &[u8]) -> Vec<u8> {
+        let mut stack: Vec<U256> = vec![];
+        let mut memory: Vec<u8> = vec![];
+        let mut pc = 0;
+        loop {
+            let op = init_code[pc];
+            pc += 1;
+            match op {
+                0x60 => {
+                    stack.push(U256::from(init_code[pc]));
+                    pc += 1;
+                }
+                0x7f => {
+                    stack.push(U256::from_big_endian(&init_code[pc..pc + 32]));
+                    pc += 32;
+                }
+                0x80 => stack.push(*stack.last().unwrap()),
+                0x39 => {
+                    let dest = stack.pop().unwrap().as_usize();
+                    let offset = stack.pop().unwrap().as_usize();
+                    let len = stack.pop().unwrap().as_usize();
+                    memory.resize(memory.len().max(dest + len), 0);
+                    memory[dest..dest + len].copy_from_slice(&init_code[offset..offset + len]);
+                }
+                0xf3 => {
+                    let offset = stack.pop().unwrap().as_usize();
+                    let len = stack.pop().unwrap().as_usize();
+                    return memory[offset..offset + len].to_vec();
+                }
+                op => panic!("unexpected opcode {op:#04x}"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_contract_deployment_calldata() -> Result<()> {
+        let code = b"\0asm\x01\0\0\0 trivial code blob".to_vec();
+        for compression in [Compression::Brotli, Compression::Zstd] {
+            let init_code = contract_deployment_calldata(&code, compression)?;
+            assert_eq!(run_init_code(&init_code), code);
+            assert_eq!(extract_compressed_wasm(&init_code), code);
+            assert_eq!(extract_compression(&init_code)?, compression);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_calldata_with_constructor_args() -> Result<()> {
+        let code = b"\0asm\x01\0\0\0 trivial code blob".to_vec();
+        let args = [0x11; 64];
+        let mut init_code = contract_deployment_calldata(&code, Compression::Zstd)?;
+        init_code.extend(args);
+        assert_eq!(run_init_code(&init_code), code);
+        assert!(has_deployment_prelude(&init_code));
+        assert_eq!(extract_compression(&init_code)?, Compression::Zstd);
+        assert_eq!(extract_compressed_wasm(&init_code), code);
+        assert_eq!(extract_constructor_args(&init_code), args);
+
+        // A code length running past the end of the calldata isn't a prelude.
+        let truncated = &init_code[..deployment_prelude_len() + code.len() - 1];
+        assert!(!has_deployment_prelude(truncated));
+        Ok(())
+    }
+
+    #[test]
+    fn test_deployment_calldata_size_limit() {
+        let code = vec![0; MAX_DEPLOYMENT_CODE_BYTES];
+        assert!(contract_deployment_calldata(&code, Compression::Brotli).is_ok());
+        let code = vec![0; MAX_DEPLOYMENT_CODE_BYTES + 1];
+        assert!(contract_deployment_calldata(&code, Compression::Brotli).is_err());
+    }
+
+    #[test]
+    fn test_raw_deployment_calldata() -> Result<()> {
+        let code = b"\0asm\x01\0\0\0 trivial code blob".to_vec();
+        let calldata = deployment_calldata(&code, Compression::Brotli, true)?;
+        assert_eq!(calldata, code);
+        assert!(!has_deployment_prelude(&calldata));
+        assert!(extract_contract_evm_deployment_prelude(&calldata).is_empty());
+        assert_eq!(extract_compressed_wasm(&calldata), code);
+        assert!(extract_constructor_args(&calldata).is_empty());
+        assert!(extract_compression(&calldata).is_err());
+
+        let calldata = deployment_calldata(&code, Compression::Brotli, false)?;
+        assert!(has_deployment_prelude(&calldata));
+        Ok(())
+    }
+}
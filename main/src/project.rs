@@ -3,16 +3,15 @@
 
 use crate::util::{color::Color, sys};
 use crate::{
-    constants::{
-        BROTLI_COMPRESSION_LEVEL, PROJECT_HASH_SECTION_NAME, RUST_TARGET, TOOLCHAIN_FILE_NAME,
-    },
+    compression::{compress_wasm, strip_user_metadata, Compression},
+    constants::{RUST_TARGET, TOOLCHAIN_FILE_NAME},
     macros::*,
 };
-use brotli2::read::BrotliEncoder;
 use eyre::{bail, eyre, Result, WrapErr};
 use glob::glob;
+use std::process::{Command, Stdio};
 use std::{
-    env::current_dir,
+    env::{self, current_dir},
     fs,
     io::Read,
     path::{Path, PathBuf},
@@ -20,11 +19,8 @@ use std::{
     sync::mpsc,
     thread,
 };
-use std::{ops::Range, process::Command};
 use tiny_keccak::{Hasher, Keccak};
 use toml::Value;
-use wasm_encoder::{Module, RawSection};
-use wasmparser::{Parser, Payload};
 
 #[derive(Default, Clone, PartialEq)]
 pub enum OptLevel {
@@ -38,6 +34,17 @@ pub struct BuildConfig {
     pub opt_level: OptLevel,
     pub stable: bool,
     pub features: Option<String>,
+    pub compression: Compression,
+    /// Cargo profile to build with, defaulting to `release`.
+    pub profile: Option<String>,
+    /// Extra flags appended to `RUSTFLAGS` for the cargo build.
+    pub rustflags: Option<String>,
+    /// Deny warnings, failing the build if any are emitted.
+    pub fail_on_warnings: bool,
+    /// Binary target to build instead of the library, for crates producing several WASMs.
+    pub bin: Option<String>,
+    /// Don't print progress, for modes whose output is read by scripts.
+    pub quiet: bool,
 }
 
 impl BuildConfig {
@@ -47,12 +54,30 @@ impl BuildConfig {
             ..Default::default()
         }
     }
+
+    fn profile(&self) -> &str {
+        self.profile.as_deref().unwrap_or("release")
+    }
+
+    /// The target subdirectory cargo writes the profile's artifacts to.
+    fn profile_dir(&self) -> &str {
+        match self.profile() {
+            "dev" | "test" => "debug",
+            "bench" => "release",
+            profile => profile,
+        }
+    }
 }
 
 #[derive(thiserror::Error, Debug, PartialEq, Eq, Clone)]
 pub enum BuildError {
     #[error("could not find WASM in release dir ({path}).")]
     NoWasmFound { path: PathBuf },
+    #[error("no WASM named {name} was built, select one of: {}", candidates.join(", "))]
+    AmbiguousWasm {
+        name: String,
+        candidates: Vec<String>,
+    },
 }
 
 /// Build a Rust project to WASM and return the path to the compiled WASM file.
@@ -64,17 +89,22 @@ pub fn build_dylib(cfg: BuildConfig) -> Result<PathBuf> {
     // Enforce a version is included in the Cargo.toml file.
     let cargo_toml_path = cwd.join(Path::new("Cargo.toml"));
     let cargo_toml_version = extract_cargo_toml_version(&cargo_toml_path)?;
-    greyln!("Building project with Cargo.toml version: {cargo_toml_version}");
+    if !cfg.quiet {
+        greyln!("Building project with Cargo.toml version: {cargo_toml_version}");
+    }
 
     let project_name = extract_cargo_project_name(&cargo_toml_path)?
         .replace("-", "_")
         .replace("\"", "");
 
     cmd.arg("build");
-    cmd.arg("--lib");
+    match &cfg.bin {
+        Some(bin) => cmd.arg("--bin").arg(bin),
+        None => cmd.arg("--lib"),
+    };
     cmd.arg("--locked");
 
-    if let Some(features) = cfg.features {
+    if let Some(features) = &cfg.features {
         cmd.arg(format!("--features={}", features.clone()));
     }
 
@@ -85,13 +115,25 @@ pub fn build_dylib(cfg: BuildConfig) -> Result<PathBuf> {
         cmd.arg("build-std-features=panic_immediate_abort");
     }
 
+    let mut rustflags: Vec<String> = cfg.rustflags.iter().cloned().collect();
+    if cfg.fail_on_warnings {
+        rustflags.push("-D warnings".to_string());
+    }
+    if !rustflags.is_empty() {
+        let existing = env::var("RUSTFLAGS").unwrap_or_default();
+        cmd.env(
+            "RUSTFLAGS",
+            format!("{existing} {}", rustflags.join(" ")).trim(),
+        );
+    }
+
     if cfg.opt_level == OptLevel::Z {
         cmd.arg("--config");
-        cmd.arg("profile.release.opt-level='z'");
+        cmd.arg(format!("profile.{}.opt-level='z'", cfg.profile()));
     }
 
     let output = cmd
-        .arg("--release")
+        .arg(format!("--profile={}", cfg.profile()))
         .arg(format!("--target={RUST_TARGET}"))
         .output()
         .wrap_err("failed to execute cargo build")?;
@@ -104,7 +146,7 @@ pub fn build_dylib(cfg: BuildConfig) -> Result<PathBuf> {
     let release_path = cwd
         .join("target")
         .join(RUST_TARGET)
-        .join("release")
+        .join(cfg.profile_dir())
         .join("deps");
 
     // Gets the files in the release folder.
@@ -115,28 +157,84 @@ pub fn build_dylib(cfg: BuildConfig) -> Result<PathBuf> {
         .filter(|r| r.is_file())
         .collect();
 
-    let wasm_file_path = release_files
-        .into_iter()
-        .find(|p| {
-            if let Some(filename) = p.file_name() {
-                let mut expected_name = project_name.clone();
-                expected_name.push_str(".wasm");
-
-                filename.to_string_lossy().ends_with(&expected_name)
-            } else {
-                false
-            }
-        })
+    let artifact_name = match &cfg.bin {
+        Some(bin) => bin.replace('-', "_"),
+        None => project_name,
+    };
+    let wasm_file_path = select_wasm(&release_files, &artifact_name)?
         .ok_or(BuildError::NoWasmFound { path: release_path })?;
 
-    let (wasm, code) =
-        compress_wasm(&wasm_file_path, [0u8; 32]).wrap_err("failed to compress WASM")?;
+    let (wasm, code) = compress_wasm(&wasm_file_path, Some([0u8; 32]), cfg.compression, true)
+        .wrap_err("failed to compress WASM")?;
 
-    println!("CONTRACT_SIZE: {}", code.len());
-    println!("WASM_SIZE: {}", wasm.len());
+    if !cfg.quiet {
+        println!("CONTRACT_SIZE: {}", code.len());
+        println!("WASM_SIZE: {}", wasm.len());
+    }
     Ok(wasm_file_path)
 }
 
+/// Picks the WASM built for the named artifact out of cargo's deps dir, erroring with the
+/// artifacts that were built when none match, so the wrong one is never silently used.
+fn select_wasm(files: &[PathBuf], name: &str) -> Result<Option<PathBuf>, BuildError> {
+    // Cargo names outputs `{artifact}-{hash}.wasm`.
+    fn artifact(path: &Path) -> Option<&str> {
+        let stem = path.file_stem()?.to_str()?;
+        (path.extension()? == "wasm").then(|| stem.rsplit_once('-').map_or(stem, |(a, _)| a))
+    }
+    if let Some(path) = files.iter().find(|p| artifact(p) == Some(name)) {
+        return Ok(Some(path.clone()));
+    }
+    let mut candidates: Vec<String> = files
+        .iter()
+        .filter_map(|path| artifact(path))
+        .map(str::to_string)
+        .collect();
+    if candidates.is_empty() {
+        return Ok(None);
+    }
+    candidates.sort();
+    candidates.dedup();
+    Err(BuildError::AmbiguousWasm {
+        name: name.to_string(),
+        candidates,
+    })
+}
+
+/// Runs binaryen's `wasm-opt -Oz` over a WASM file and returns the path of the optimized output.
+/// The project hash section is added later during compression, so it is unaffected.
+pub fn optimize_wasm(wasm: &Path) -> Result<PathBuf> {
+    if !sys::command_exists("wasm-opt") {
+        let link = "https://github.com/WebAssembly/binaryen#releases".red();
+        bail!("wasm-opt not found. Please install binaryen, see\n{link}");
+    }
+    let optimized = wasm.with_extension("opt.wasm");
+    let output = sys::new_command("wasm-opt")
+        .arg(wasm)
+        .arg("-Oz")
+        .arg("-o")
+        .arg(&optimized)
+        .output()
+        .wrap_err("failed to execute wasm-opt")?;
+    if !output.status.success() {
+        bail!("wasm-opt command failed");
+    }
+    Ok(optimized)
+}
+
+/// Strips every custom section except the project hash from a WASM file, such as the
+/// `.debug_*` and `name` sections of debug builds. Returns the path of the stripped output
+/// and the number of bytes removed.
+pub fn strip_wasm(wasm: &Path) -> Result<(PathBuf, usize)> {
+    let bytes =
+        fs::read(wasm).wrap_err_with(|| eyre!("failed to read Wasm {}", wasm.to_string_lossy()))?;
+    let stripped = strip_user_metadata(&bytes, true).wrap_err("failed to strip wasm file")?;
+    let output = wasm.with_extension("stripped.wasm");
+    fs::write(&output, &stripped)
+        .wrap_err_with(|| eyre!("failed to write Wasm {}", output.to_string_lossy()))?;
+    Ok((output, bytes.len().saturating_sub(stripped.len())))
+}
+
 fn all_paths(root_dir: &Path, source_file_patterns: Vec<String>) -> Result<Vec<PathBuf>> {
     let mut files = Vec::<PathBuf>::new();
     let mut directories = Vec::<PathBuf>::new();
@@ -217,6 +315,42 @@ pub fn extract_toolchain_channel(toolchain_file_path: &PathBuf) -> Result<String
     Ok(channel)
 }
 
+/// Fails fast if the toolchain channel isn't installed, rather than midway through the
+/// cargo build, or installs it through rustup when `auto_install` is set.
+pub fn ensure_toolchain_installed(channel: &str, auto_install: bool) -> Result<()> {
+    let output = sys::new_command("rustup")
+        .args(["toolchain", "list"])
+        .stdout(Stdio::piped())
+        .output()
+        .wrap_err("failed to execute rustup toolchain list")?;
+    if !output.status.success() {
+        bail!("rustup toolchain list failed");
+    }
+    if toolchain_listed(&String::from_utf8_lossy(&output.stdout), channel) {
+        return Ok(());
+    }
+    if !auto_install {
+        bail!("toolchain {channel} not installed; run rustup toolchain install {channel}");
+    }
+    greyln!("installing toolchain {}", channel.lavender());
+    let status = sys::new_command("rustup")
+        .args(["toolchain", "install", channel])
+        .status()
+        .wrap_err("failed to execute rustup toolchain install")?;
+    if !status.success() {
+        bail!("failed to install toolchain {channel}");
+    }
+    Ok(())
+}
+
+/// Whether the output of `rustup toolchain list` includes the channel, whose entries
+/// are suffixed with the host triple, e.g. `1.80.0-x86_64-unknown-linux-gnu (default)`.
+fn toolchain_listed(list: &str, channel: &str) -> bool {
+    list.lines()
+        .filter_map(|line| line.split_whitespace().next())
+        .any(|name| name == channel || name.starts_with(&format!("{channel}-")))
+}
+
 pub fn extract_cargo_toml_version(cargo_toml_path: &PathBuf) -> Result<String> {
     let cargo_toml_contents = fs::read_to_string(cargo_toml_path)
         .context("expected to find a Cargo.toml file in project directory")?;
@@ -307,12 +441,15 @@ pub fn hash_files(
 
     // Read the file contents in another thread and process the keccak in the main thread.
     let (tx, rx) = mpsc::channel();
+    let quiet = cfg.quiet;
     thread::spawn(move || {
         for filename in paths.iter() {
-            greyln!(
-                "File used for deployment hash: {}",
-                filename.as_os_str().to_string_lossy()
-            );
+            if !quiet {
+                greyln!(
+                    "File used for deployment hash: {}",
+                    filename.as_os_str().to_string_lossy()
+                );
+            }
             tx.send(read_file_preimage(filename))
                 .expect("failed to send preimage (impossible)");
         }
@@ -323,10 +460,12 @@ pub fn hash_files(
 
     let mut hash = [0u8; 32];
     keccak.finalize(&mut hash);
-    greyln!(
-        "project metadata hash computed on deployment: {:?}",
-        hex::encode(hash)
-    );
+    if !cfg.quiet {
+        greyln!(
+            "project metadata hash computed on deployment: {:?}",
+            hex::encode(hash)
+        );
+    }
     Ok(hash)
 }
 
@@ -343,108 +482,15 @@ fn expand_glob_patterns(patterns: Vec<String>) -> Result<Vec<PathBuf>> {
     Ok(files_to_include)
 }
 
-/// Reads a WASM file at a specified path and returns its brotli compressed bytes.
-pub fn compress_wasm(wasm: &PathBuf, project_hash: [u8; 32]) -> Result<(Vec<u8>, Vec<u8>)> {
-    let wasm =
-        fs::read(wasm).wrap_err_with(|| eyre!("failed to read Wasm {}", wasm.to_string_lossy()))?;
-
-    // We convert the WASM from binary to text and back to binary as this trick removes any dangling
-    // mentions of reference types in the wasm body, which are not yet supported by Arbitrum chain backends.
-    let wat_str =
-        wasmprinter::print_bytes(&wasm).map_err(|e| eyre!("failed to convert Wasm to Wat: {e}"))?;
-    let wasm = wasmer::wat2wasm(wat_str.as_bytes())
-        .map_err(|e| eyre!("failed to convert Wat to Wasm: {e}"))?;
-
-    // We include the project's hash as a custom section
-    // in the user's WASM so it can be verified by Cargo stylus'
-    // reproducible verification. This hash is added as a section that is
-    // ignored by WASM runtimes, so it will only exist in the file
-    // for metadata purposes.
-    let wasm = add_project_hash_to_wasm_file(&wasm, project_hash)
-        .wrap_err("failed to add project hash to wasm file as custom section")?;
-
-    let wasm =
-        strip_user_metadata(&wasm).wrap_err("failed to strip user metadata from wasm file")?;
-
-    let wasm = wasmer::wat2wasm(&wasm).wrap_err("failed to parse Wasm")?;
-
-    let mut compressor = BrotliEncoder::new(&*wasm, BROTLI_COMPRESSION_LEVEL);
-    let mut compressed_bytes = vec![];
-    compressor
-        .read_to_end(&mut compressed_bytes)
-        .wrap_err("failed to compress WASM bytes")?;
-
-    Ok((wasm.to_vec(), compressed_bytes))
-}
-
-// Adds the hash of the project's source files to the wasm as a custom section
-// if it does not already exist. This allows for reproducible builds by cargo stylus
-// for all Rust stylus contracts. See `cargo stylus verify --help` for more information.
-fn add_project_hash_to_wasm_file(
-    wasm_file_bytes: &[u8],
-    project_hash: [u8; 32],
-) -> Result<Vec<u8>> {
-    let section_exists = has_project_hash_section(wasm_file_bytes)?;
-    if section_exists {
-        greyln!("Wasm file bytes already contains a custom section with a project hash, not overwriting'");
-        return Ok(wasm_file_bytes.to_vec());
-    }
-    Ok(add_custom_section(wasm_file_bytes, project_hash))
-}
-
-pub fn has_project_hash_section(wasm_file_bytes: &[u8]) -> Result<bool> {
+/// Counts the functions defined by a WASM, excluding imports.
+pub fn count_wasm_functions(wasm: &[u8]) -> Result<u32> {
     let parser = wasmparser::Parser::new(0);
-    for payload in parser.parse_all(wasm_file_bytes) {
-        if let wasmparser::Payload::CustomSection(reader) = payload? {
-            if reader.name() == PROJECT_HASH_SECTION_NAME {
-                println!(
-                    "Found the project hash custom section name {}",
-                    hex::encode(reader.data())
-                );
-                return Ok(true);
-            }
-        }
-    }
-    Ok(false)
-}
-
-fn add_custom_section(wasm_file_bytes: &[u8], project_hash: [u8; 32]) -> Vec<u8> {
-    let mut bytes = vec![];
-    bytes.extend_from_slice(wasm_file_bytes);
-    wasm_gen::write_custom_section(&mut bytes, PROJECT_HASH_SECTION_NAME, &project_hash);
-    bytes
-}
-
-fn strip_user_metadata(wasm_file_bytes: &[u8]) -> Result<Vec<u8>> {
-    let mut module = Module::new();
-    // Parse the input WASM and iterate over the sections
-    let parser = Parser::new(0);
-    for payload in parser.parse_all(wasm_file_bytes) {
-        match payload? {
-            Payload::CustomSection { .. } => {
-                // Skip custom sections to remove sensitive metadata
-                greyln!("stripped custom section from user wasm to remove any sensitive data");
-            }
-            Payload::UnknownSection { .. } => {
-                // Skip unknown sections that might not be sensitive
-                println!("stripped unknown section from user wasm to remove any sensitive data");
-            }
-            item => {
-                // Handle other sections as normal.
-                if let Some(section) = item.as_section() {
-                    let (id, range): (u8, Range<usize>) = section;
-                    let data_slice = &wasm_file_bytes[range.start..range.end]; // Start at the beginning of the range
-                    let raw_section = RawSection {
-                        id,
-                        data: data_slice,
-                    };
-                    module.section(&raw_section);
-                }
-            }
+    for payload in parser.parse_all(wasm) {
+        if let wasmparser::Payload::FunctionSection(reader) = payload? {
+            return Ok(reader.count());
         }
     }
-    // Return the stripped WASM binary
-    Ok(module.finish())
+    Ok(0)
 }
 
 #[cfg(test)]
@@ -458,6 +504,25 @@ mod test {
     };
     use tempfile::{tempdir, TempDir};
 
+    #[test]
+    fn test_select_wasm() {
+        let files: Vec<PathBuf> = ["token.wasm", "token.d", "vault-1a2b.wasm", "libdep.rlib"]
+            .iter()
+            .map(PathBuf::from)
+            .collect();
+        let selected = select_wasm(&files, "token").unwrap();
+        assert_eq!(selected, Some(PathBuf::from("token.wasm")));
+        let selected = select_wasm(&files, "vault").unwrap();
+        assert_eq!(selected, Some(PathBuf::from("vault-1a2b.wasm")));
+
+        let err = select_wasm(&files, "other").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "no WASM named other was built, select one of: token, vault"
+        );
+        assert_eq!(select_wasm(&files[1..2], "token").unwrap(), None);
+    }
+
     #[cfg(feature = "nightly")]
     extern crate test;
 
@@ -587,6 +652,25 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_toolchain_listed() {
+        let list = "stable-x86_64-unknown-linux-gnu\n\
+                    1.80.0-x86_64-unknown-linux-gnu (default)\n\
+                    nightly-2024-08-01-x86_64-unknown-linux-gnu\n";
+        assert!(toolchain_listed(list, "1.80.0"));
+        assert!(toolchain_listed(list, "nightly-2024-08-01"));
+        assert!(!toolchain_listed(list, "1.80.1"));
+        assert!(!toolchain_listed(list, "1.8"));
+    }
+
+    #[test]
+    fn test_count_wasm_functions() -> Result<()> {
+        assert_eq!(count_wasm_functions(b"\0asm\x01\0\0\0")?, 0);
+        let wasm = wasmer::wat2wasm(br#"(module (import "e" "f" (func)) (func) (func))"#)?;
+        assert_eq!(count_wasm_functions(&wasm)?, 2);
+        Ok(())
+    }
+
     #[cfg(feature = "nightly")]
     #[bench]
     pub fn bench_hash_files(b: &mut test::Bencher) -> Result<()> {
@@ -0,0 +1,102 @@
+// Copyright 2023-2024, Offchain Labs, Inc.
+// For licensing, see https://github.com/OffchainLabs/cargo-stylus/blob/main/licenses/COPYRIGHT.md
+
+use crate::compression::Compression;
+use ethers::types::{H160, H256, U256};
+use serde::Serialize;
+
+/// Summary of a contract that passed `cargo stylus check`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CheckReport {
+    /// Hex-encoded hash of the project's source files.
+    pub project_hash: String,
+    /// Size in bytes of the processed WASM.
+    pub wasm_size: usize,
+    /// Size in bytes of the compressed WASM.
+    pub compressed_size: usize,
+    /// Scheme the WASM was compressed with.
+    pub compression: Compression,
+    /// Whether the deployment calldata is the code itself, without the prelude.
+    pub raw_code: bool,
+    /// Data fee in wei, as a decimal string.
+    pub data_fee_wei: String,
+    /// Hex-encoded deployment calldata: the prelude followed by the compressed WASM.
+    pub deployment_calldata: String,
+}
+
+/// Summary of a `cargo stylus deploy` run.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DeployReport {
+    /// The check of the deployed contract, absent when resuming an earlier deployment tx.
+    pub check: Option<CheckReport>,
+    /// Address of the deployed contract, or the predicted one when only estimating. Absent
+    /// when the deployment was proposed to a Safe, as it's only known once executed.
+    pub contract_address: Option<H160>,
+    /// Hash of the deployment tx, absent when only estimating.
+    pub tx_hash: Option<H256>,
+    pub gas_estimate: U256,
+    pub gas_used: Option<U256>,
+    pub effective_gas_price: Option<U256>,
+    /// What the deployment tx actually cost: gas used times effective gas price.
+    pub cost_wei: Option<U256>,
+    /// Commit checked out when deploying, absent outside a git repository.
+    pub git_commit: Option<String>,
+    /// The calls made to the contract after it was deployed, in the order they were sent.
+    pub calls: Vec<CallReport>,
+}
+
+/// Summary of a call made to a freshly deployed contract.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CallReport {
+    pub name: String,
+    pub tx_hash: H256,
+    pub gas_used: Option<U256>,
+}
+
+/// A check a contract failed, e.g. exceeding the code size limit.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Diagnostic {
+    pub rule: Rule,
+    pub message: String,
+    /// File the diagnostic is reported against, relative to the project directory.
+    pub file: String,
+}
+
+impl Diagnostic {
+    pub fn new(rule: Rule, message: String, file: &str) -> Self {
+        Self {
+            rule,
+            message,
+            file: file.to_string(),
+        }
+    }
+}
+
+/// The checks `cargo stylus check` runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Rule {
+    CodeSize,
+    FunctionCount,
+    Toolchain,
+}
+
+impl Rule {
+    pub const ALL: [Rule; 3] = [Rule::CodeSize, Rule::FunctionCount, Rule::Toolchain];
+
+    pub fn id(self) -> &'static str {
+        match self {
+            Rule::CodeSize => "code-size",
+            Rule::FunctionCount => "function-count",
+            Rule::Toolchain => "toolchain",
+        }
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            Rule::CodeSize => "The compressed WASM must fit within the code size limit",
+            Rule::FunctionCount => "The WASM must not define more functions than the limit",
+            Rule::Toolchain => "The project must pin an installed toolchain in rust-toolchain.toml",
+        }
+    }
+}
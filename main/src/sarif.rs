@@ -0,0 +1,79 @@
+// Copyright 2023-2024, Offchain Labs, Inc.
+// For licensing, see https://github.com/OffchainLabs/cargo-stylus/blob/main/licenses/COPYRIGHT.md
+
+use crate::report::{Diagnostic, Rule};
+use eyre::{Result, WrapErr};
+use serde_json::{json, Value};
+use std::{fs, path::Path};
+
+const SARIF_SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+
+/// Renders check diagnostics as a SARIF log, the format code-scanning UIs read to annotate
+/// files with findings.
+pub fn to_sarif(diagnostics: &[Diagnostic]) -> Value {
+    let rules: Vec<_> = Rule::ALL
+        .iter()
+        .map(|rule| {
+            json!({
+                "id": rule.id(),
+                "shortDescription": { "text": rule.description() },
+            })
+        })
+        .collect();
+    let results: Vec<_> = diagnostics
+        .iter()
+        .map(|diagnostic| {
+            json!({
+                "ruleId": diagnostic.rule.id(),
+                "level": "error",
+                "message": { "text": diagnostic.message },
+                "locations": [{
+                    "physicalLocation": {
+                        "artifactLocation": { "uri": diagnostic.file },
+                    },
+                }],
+            })
+        })
+        .collect();
+    json!({
+        "$schema": SARIF_SCHEMA,
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "cargo-stylus",
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "rules": rules,
+                },
+            },
+            "results": results,
+        }],
+    })
+}
+
+pub fn write_sarif(path: &Path, diagnostics: &[Diagnostic]) -> Result<()> {
+    let sarif = serde_json::to_string_pretty(&to_sarif(diagnostics))?;
+    fs::write(path, sarif).wrap_err("failed to write SARIF")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_to_sarif() {
+        let message = "compressed wasm size 30000 B exceeds the limit of 24576 B";
+        let diagnostic = Diagnostic::new(Rule::CodeSize, message.to_string(), "Cargo.toml");
+        let sarif = to_sarif(&[diagnostic]);
+        let run = &sarif["runs"][0];
+        assert_eq!(run["tool"]["driver"]["rules"].as_array().unwrap().len(), 3);
+        let result = &run["results"][0];
+        assert_eq!(result["ruleId"], "code-size");
+        assert_eq!(result["message"]["text"], message);
+        let location = &result["locations"][0]["physicalLocation"]["artifactLocation"];
+        assert_eq!(location["uri"], "Cargo.toml");
+
+        let sarif = to_sarif(&[]);
+        assert!(sarif["runs"][0]["results"].as_array().unwrap().is_empty());
+    }
+}
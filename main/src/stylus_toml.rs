@@ -0,0 +1,128 @@
+// Copyright 2023-2024, Offchain Labs, Inc.
+// For licensing, see https://github.com/OffchainLabs/cargo-stylus/blob/main/licenses/COPYRIGHT.md
+
+use crate::{constants::STYLUS_TOML_FILE_NAME, macros::*, util::color::Color};
+use eyre::{eyre, Result, WrapErr};
+use serde::Deserialize;
+use std::{env, fs, path::Path};
+
+/// Project defaults from a `Stylus.toml` in the project root, keyed like the flags they
+/// stand in for, e.g. `max-fee-per-gas-gwei = 2`.
+#[derive(Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct StylusToml {
+    endpoint: Option<OneOrMany>,
+    // TOML integers are 64-bit, so this can't be the flag's u128.
+    max_fee_per_gas_gwei: Option<u64>,
+    features: Option<OneOrMany>,
+    wasm_file: Option<String>,
+    verbose: Option<bool>,
+    source_files_for_project_hash: Option<Vec<String>>,
+}
+
+/// A value given either alone or as a list, e.g. a single endpoint or several to fail over.
+#[derive(Debug, PartialEq, Deserialize)]
+#[serde(untagged)]
+enum OneOrMany {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl OneOrMany {
+    fn join(&self) -> String {
+        match self {
+            Self::One(value) => value.clone(),
+            Self::Many(values) => values.join(","),
+        }
+    }
+}
+
+impl StylusToml {
+    /// The values to set the environment variables backing the flags to.
+    fn env_vars(&self) -> Vec<(&'static str, String)> {
+        let mut vars = vec![];
+        if let Some(endpoint) = &self.endpoint {
+            vars.push(("PHAROS_RPC_URL", endpoint.join()));
+        }
+        if let Some(max_fee) = self.max_fee_per_gas_gwei {
+            vars.push(("PHAROS_MAX_FEE_PER_GAS_GWEI", max_fee.to_string()));
+        }
+        if let Some(features) = &self.features {
+            vars.push(("PHAROS_FEATURES", features.join()));
+        }
+        if let Some(wasm_file) = &self.wasm_file {
+            vars.push(("PHAROS_WASM_FILE", wasm_file.clone()));
+        }
+        if let Some(verbose) = self.verbose {
+            vars.push(("PHAROS_VERBOSE", verbose.to_string()));
+        }
+        if let Some(files) = &self.source_files_for_project_hash {
+            vars.push(("PHAROS_SOURCE_FILES_FOR_PROJECT_HASH", files.join(",")));
+        }
+        vars
+    }
+}
+
+/// Loads the defaults in `Stylus.toml`, if the project has one. Like `load_env_file`, this
+/// runs ahead of argument parsing, and backs flags through environment variables. Any that
+/// are already set win, so flags override the environment, which overrides the file.
+pub fn load_stylus_toml(verbose: bool) -> Result<()> {
+    let path = Path::new(STYLUS_TOML_FILE_NAME);
+    if !path.is_file() {
+        return Ok(());
+    }
+    let contents = fs::read_to_string(path).wrap_err("failed to read Stylus.toml")?;
+    let config: StylusToml =
+        toml::from_str(&contents).map_err(|e| eyre!("failed to parse Stylus.toml: {e}"))?;
+    for (key, value) in config.env_vars() {
+        if env::var_os(key).is_none() {
+            env::set_var(key, value);
+        }
+    }
+    if verbose {
+        greyln!(
+            "loaded project defaults from {}",
+            STYLUS_TOML_FILE_NAME.lavender()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_env_vars() -> Result<()> {
+        let config: StylusToml = toml::from_str(
+            r#"
+            endpoint = ["http://localhost:8547", "http://localhost:8548"]
+            max-fee-per-gas-gwei = 2
+            features = "export-abi"
+            verbose = true
+            source-files-for-project-hash = ["src/**/*.rs", "Cargo.toml"]
+            "#,
+        )?;
+        let vars = config.env_vars();
+        assert_eq!(
+            vars,
+            [
+                (
+                    "PHAROS_RPC_URL",
+                    "http://localhost:8547,http://localhost:8548".to_string()
+                ),
+                ("PHAROS_MAX_FEE_PER_GAS_GWEI", "2".to_string()),
+                ("PHAROS_FEATURES", "export-abi".to_string()),
+                ("PHAROS_VERBOSE", "true".to_string()),
+                (
+                    "PHAROS_SOURCE_FILES_FOR_PROJECT_HASH",
+                    "src/**/*.rs,Cargo.toml".to_string()
+                ),
+            ]
+        );
+
+        assert!(toml::from_str::<StylusToml>("endpont = \"http://localhost:8547\"").is_err());
+        assert_eq!(toml::from_str::<StylusToml>("")?, StylusToml::default());
+        Ok(())
+    }
+}
@@ -3,7 +3,10 @@
 
 #![allow(dead_code)]
 
-use std::fmt::{Debug, Display};
+use std::{
+    fmt::{Debug, Display},
+    sync::atomic::{AtomicBool, Ordering},
+};
 
 pub const BLUE: &str = "\x1b[34;1m";
 pub const DIM: &str = "\x1b[2m";
@@ -16,6 +19,18 @@ pub const WHITE: &str = "\x1b[0;1m";
 pub const YELLOW: &str = "\x1b[33;1m";
 pub const LAVENDER: &str = "\x1b[38;5;183;1m";
 
+static SILENCED: AtomicBool = AtomicBool::new(false);
+
+/// Silences `greyln!`, leaving stdout to machine-readable output like `--json` reports.
+pub fn silence() {
+    SILENCED.store(true, Ordering::Relaxed);
+}
+
+/// Whether human-readable output has been silenced.
+pub fn silenced() -> bool {
+    SILENCED.load(Ordering::Relaxed)
+}
+
 pub trait Color {
     fn color(&self, color: &str) -> String;
 
@@ -2,5 +2,7 @@
 // For licensing, see https://github.com/OffchainLabs/cargo-stylus/blob/main/licenses/COPYRIGHT.md
 
 pub mod color;
+pub mod spinner;
 pub mod sys;
 pub mod text;
+pub mod timer;
@@ -0,0 +1,46 @@
+// Copyright 2024, Offchain Labs, Inc.
+// For licensing, see https://github.com/OffchainLabs/cargo-stylus/blob/main/licenses/COPYRIGHT.md
+
+use crate::util::color::Color;
+use std::{
+    io::{self, IsTerminal, Write},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+const FRAMES: [char; 4] = ['|', '/', '-', '\\'];
+
+/// Runs a slow step while animating a spinner with a message, so it doesn't look hung.
+/// The spinner is cleared once the step completes, and skipped when stdout isn't a terminal.
+pub fn with_spinner<T>(msg: &str, quiet: bool, step: impl FnOnce() -> T) -> T {
+    if quiet || !io::stdout().is_terminal() {
+        return step();
+    }
+    let done = Arc::new(AtomicBool::new(false));
+    let spinner = {
+        let done = done.clone();
+        let msg = msg.grey();
+        thread::spawn(move || {
+            let mut stdout = io::stdout();
+            for frame in FRAMES.iter().cycle() {
+                if done.load(Ordering::Relaxed) {
+                    break;
+                }
+                let _ = write!(stdout, "\r{frame} {msg}");
+                let _ = stdout.flush();
+                thread::sleep(Duration::from_millis(100));
+            }
+            // Clear the line for whatever is printed next.
+            let _ = write!(stdout, "\r\x1b[2K");
+            let _ = stdout.flush();
+        })
+    };
+    let result = step();
+    done.store(true, Ordering::Relaxed);
+    let _ = spinner.join();
+    result
+}
@@ -1,23 +1,263 @@
 // Copyright 2023-2024, Offchain Labs, Inc.
 // For licensing, see https://github.com/OffchainLabs/cargo-stylus/blob/main/licenses/COPYRIGHT.md
 
+use crate::{macros::*, util::color::Color};
+use async_trait::async_trait;
 use ethers::{prelude::*, providers::Provider};
-use eyre::{Context, Result};
+use eyre::{bail, Context, Result};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION};
+use serde::{de::DeserializeOwned, Serialize};
 use std::{
+    env,
     ffi::OsStr,
+    fmt::Debug,
     fs::File,
     io::{self, Write},
     path::PathBuf,
-    process::{Command, Stdio},
-    time::Duration,
+    process::{Child, Command, ExitStatus, Stdio},
+    str::FromStr,
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
-pub fn new_provider(url: &str) -> Result<Provider<Http>> {
-    let mut provider = Provider::<Http>::try_from(url).wrap_err("failed to init http provider")?;
-    provider.set_interval(Duration::from_millis(250));
+/// Transport to an RPC endpoint, picked by the scheme of its URL.
+#[derive(Clone, Debug)]
+pub enum RpcClient {
+    Http(Http),
+    Ws(Ws),
+    Ipc(Ipc),
+    /// Endpoints tried in order, moving on to the next when one can't be reached or
+    /// doesn't answer with a JSON-RPC response.
+    Failover(Vec<RpcClient>),
+    /// Retries requests that fail without an answer, backing off between attempts.
+    Retry(Box<RpcClient>, RetryPolicy),
+}
+
+/// How to connect to RPC endpoints.
+#[derive(Clone, Debug, Default)]
+pub struct RpcOptions {
+    pub retry: RetryPolicy,
+    /// Extra headers sent with every HTTP request, e.g. an API key.
+    pub headers: Vec<(String, String)>,
+    /// Token sent as `Authorization: Bearer <token>` to HTTP and websocket endpoints.
+    pub bearer_token: Option<String>,
+    /// HTTP or SOCKS proxy to send HTTP requests through. Websocket endpoints can't be used
+    /// with one.
+    pub proxy: Option<String>,
+    /// How long to wait on an HTTP request before giving up on it.
+    pub timeout: Option<Duration>,
+    /// How often to poll for receipts and new blocks, or every 250ms when unset.
+    pub poll_interval: Option<Duration>,
+}
+
+/// How often providers poll by default.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// How requests to an RPC endpoint are retried.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RetryPolicy {
+    /// Attempts made after the first one fails.
+    pub retries: u32,
+    /// Delay before the first retry, doubling with each one after.
+    pub backoff: Duration,
+}
+
+impl RetryPolicy {
+    /// Exponential backoff before the given retry, with up to half of it again added as
+    /// jitter so clients that failed together don't retry in lockstep.
+    fn delay(&self, retry: u32) -> Duration {
+        let delay = self.backoff.saturating_mul(1 << retry.min(16));
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .subsec_nanos();
+        let jitter = delay.mul_f64(f64::from(nanos % 1000) / 2000.0);
+        delay + jitter
+    }
+}
+
+/// Methods not safe to repeat when it's unclear whether the first attempt reached the node.
+const UNRETRIED_METHODS: &[&str] = &["eth_sendTransaction", "eth_sendRawTransaction"];
+
+#[async_trait]
+impl JsonRpcClient for RpcClient {
+    type Error = ProviderError;
+
+    async fn request<T, R>(&self, method: &str, params: T) -> Result<R, ProviderError>
+    where
+        T: Debug + Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+    {
+        match self {
+            Self::Http(http) => Ok(JsonRpcClient::request(http, method, params).await?),
+            Self::Ws(ws) => Ok(JsonRpcClient::request(ws, method, params).await?),
+            Self::Ipc(ipc) => Ok(JsonRpcClient::request(ipc, method, params).await?),
+            Self::Failover(clients) => {
+                let params = serde_json::to_value(params)?;
+                let mut last_err = None;
+                for client in clients {
+                    match JsonRpcClient::request(client, method, &params).await {
+                        Ok(response) => return Ok(response),
+                        Err(err) if is_unreachable(&err) => {
+                            egreyln!("RPC endpoint failed, trying the next: {err}");
+                            last_err = Some(err);
+                        }
+                        Err(err) => return Err(err),
+                    }
+                }
+                Err(last_err.unwrap_or(ProviderError::CustomError("no RPC endpoints".into())))
+            }
+            Self::Retry(client, policy) => {
+                let params = serde_json::to_value(params)?;
+                let retries = match UNRETRIED_METHODS.contains(&method) {
+                    true => 0,
+                    false => policy.retries,
+                };
+                let mut retry = 0;
+                loop {
+                    match JsonRpcClient::request(client.as_ref(), method, &params).await {
+                        Err(err) if retry < retries && is_unreachable(&err) => {
+                            let delay = policy.delay(retry);
+                            egreyln!("{method} failed, retrying in {delay:?}: {err}");
+                            tokio::time::sleep(delay).await;
+                            retry += 1;
+                        }
+                        result => return result,
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Whether a request failed without the endpoint answering it, be it a connection error
+/// or a response that isn't JSON-RPC, like a 5xx page from a proxy.
+fn is_unreachable(err: &ProviderError) -> bool {
+    RpcError::as_error_response(err).is_none()
+}
+
+/// Connects to an RPC endpoint over websockets for `ws://` and `wss://` URLs, over IPC for
+/// socket paths like `/path/to/node.ipc`, and over HTTP otherwise.
+pub async fn new_provider(url: &str) -> Result<Provider<RpcClient>> {
+    let mut provider = Provider::new(connect(url, &RpcOptions::default()).await?);
+    provider.set_interval(POLL_INTERVAL);
+    Ok(provider)
+}
+
+/// Connects to the first of the endpoints that is up, failing over to the rest in order
+/// when a request can't be served, and retrying requests none of them could serve.
+pub async fn new_failover_provider(
+    urls: &[String],
+    opts: &RpcOptions,
+) -> Result<Provider<RpcClient>> {
+    let client = match urls {
+        [url] => connect(url, opts).await?,
+        urls => {
+            let mut clients = vec![];
+            for url in urls {
+                match connect(url, opts).await {
+                    Ok(client) => clients.push(client),
+                    Err(err) => egreyln!("skipping RPC endpoint {url}: {err:#}"),
+                }
+            }
+            if clients.is_empty() {
+                bail!("none of the RPC endpoints could be connected to");
+            }
+            RpcClient::Failover(clients)
+        }
+    };
+    let client = match opts.retry.retries {
+        0 => client,
+        _ => RpcClient::Retry(Box::new(client), opts.retry),
+    };
+    let mut provider = Provider::new(client);
+    provider.set_interval(opts.poll_interval.unwrap_or(POLL_INTERVAL));
     Ok(provider)
 }
 
+async fn connect(url: &str, opts: &RpcOptions) -> Result<RpcClient> {
+    let client = if is_ws_url(url) {
+        RpcClient::Ws(connect_ws(url, opts).await?)
+    } else if is_ipc_path(url) {
+        let ipc = Ipc::connect(url).await;
+        RpcClient::Ipc(ipc.wrap_err("failed to init ipc provider")?)
+    } else {
+        let url = reqwest::Url::parse(url).wrap_err("failed to init http provider")?;
+        RpcClient::Http(Http::new_with_client(url, http_client(opts)?))
+    };
+    Ok(client)
+}
+
+/// Connects to a websocket endpoint, authenticating with the bearer token if there is one.
+/// Websockets can't go through a proxy, so one given explicitly is an error rather than
+/// silently bypassed.
+async fn connect_ws(url: &str, opts: &RpcOptions) -> Result<Ws> {
+    if opts.proxy.is_some() {
+        bail!("websocket endpoint {url} can't be reached through --proxy; use an HTTP endpoint");
+    }
+    if let Some(var) = ["HTTPS_PROXY", "https_proxy", "ALL_PROXY", "all_proxy"]
+        .into_iter()
+        .find(|var| env::var_os(var).is_some())
+    {
+        egreyln!("connecting to websocket endpoint {url} directly, ignoring {var}");
+    }
+    let auth = opts.bearer_token.as_ref().map(Authorization::bearer);
+    let ws = Ws::connect(ConnectionDetails::new(url, auth)).await;
+    ws.wrap_err("failed to init ws provider")
+}
+
+fn http_client(opts: &RpcOptions) -> Result<reqwest::Client> {
+    let mut headers = HeaderMap::new();
+    for (name, value) in &opts.headers {
+        let name = HeaderName::from_str(name).wrap_err("invalid RPC header name")?;
+        let value = HeaderValue::from_str(value).wrap_err("invalid RPC header value")?;
+        headers.insert(name, value);
+    }
+    if let Some(token) = &opts.bearer_token {
+        let mut value =
+            HeaderValue::from_str(&format!("Bearer {token}")).wrap_err("invalid bearer token")?;
+        value.set_sensitive(true);
+        headers.insert(AUTHORIZATION, value);
+    }
+    let mut builder = http_client_builder(opts.proxy.as_deref())?.default_headers(headers);
+    if let Some(timeout) = opts.timeout {
+        builder = builder.timeout(timeout);
+    }
+    builder.build().wrap_err("failed to init http client")
+}
+
+/// Starts building an HTTP client that sends requests through the proxy, if one is given.
+/// Without one, the usual `HTTPS_PROXY` and `ALL_PROXY` environment variables apply.
+pub fn http_client_builder(proxy: Option<&str>) -> Result<reqwest::ClientBuilder> {
+    let builder = reqwest::Client::builder();
+    let Some(proxy) = proxy else {
+        return Ok(builder);
+    };
+    let proxy = reqwest::Proxy::all(proxy).wrap_err("invalid proxy")?;
+    Ok(builder.proxy(proxy))
+}
+
+/// Parses a `KEY:VALUE` header.
+pub fn parse_header(header: &str) -> Result<(String, String)> {
+    let Some((name, value)) = header.split_once(':') else {
+        bail!("header must be KEY:VALUE, got {header}");
+    };
+    Ok((name.trim().to_string(), value.trim().to_string()))
+}
+
+pub fn is_ws_url(url: &str) -> bool {
+    url.starts_with("ws://") || url.starts_with("wss://")
+}
+
+/// Whether the endpoint is a unix socket or windows named pipe path rather than a URL.
+pub fn is_ipc_path(url: &str) -> bool {
+    !url.contains("://") && (url.ends_with(".ipc") || url.starts_with(r"\\.\pipe\"))
+}
+
+pub async fn new_ws_provider(url: &str, opts: &RpcOptions) -> Result<Provider<Ws>> {
+    Ok(Provider::new(connect_ws(url, opts).await?))
+}
+
 pub fn new_command<S: AsRef<OsStr>>(program: S) -> Command {
     let mut command = Command::new(program);
     command.stdout(Stdio::inherit()).stderr(Stdio::inherit());
@@ -34,6 +274,23 @@ pub fn command_exists<S: AsRef<OsStr>>(program: S) -> bool {
         .unwrap_or_default()
 }
 
+/// Waits for a child process to exit, killing it if it outlives the timeout.
+/// Returns `None` when the timeout expired.
+pub fn wait_with_timeout(child: &mut Child, timeout: Duration) -> Result<Option<ExitStatus>> {
+    let start = Instant::now();
+    loop {
+        if let Some(status) = child.try_wait().wrap_err("failed to poll child process")? {
+            return Ok(Some(status));
+        }
+        if start.elapsed() >= timeout {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Ok(None);
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+}
+
 pub fn host_arch() -> Result<String> {
     rustc_host::from_cli().wrap_err_with(|| "failed to get host arch")
 }
@@ -45,3 +302,71 @@ pub fn file_or_stdout(path: Option<PathBuf>) -> Result<Box<dyn Write>> {
         None => Box::new(io::stdout().lock()),
     })
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_is_ws_url() {
+        assert!(is_ws_url("ws://localhost:8546"));
+        assert!(is_ws_url("wss://localhost:8546"));
+        assert!(!is_ws_url("https://localhost:8545"));
+        assert!(!is_ws_url("http://localhost:8545"));
+    }
+
+    #[test]
+    fn test_retry_delay() {
+        let policy = RetryPolicy {
+            retries: 3,
+            backoff: Duration::from_millis(100),
+        };
+        for (retry, base) in [(0, 100), (1, 200), (2, 400)] {
+            let delay = policy.delay(retry);
+            assert!(delay >= Duration::from_millis(base));
+            assert!(delay <= Duration::from_millis(base * 3 / 2));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_ws_rejects_proxy() {
+        let opts = RpcOptions {
+            proxy: Some("socks5://localhost:1080".to_string()),
+            ..Default::default()
+        };
+        let err = connect_ws("ws://localhost:8546", &opts).await.unwrap_err();
+        assert!(err.to_string().contains("--proxy"));
+        let urls = ["ws://localhost:8546".to_string()];
+        assert!(new_failover_provider(&urls, &opts).await.is_err());
+    }
+
+    #[test]
+    fn test_parse_header() -> Result<()> {
+        let header = parse_header("x-api-key: abc:123")?;
+        assert_eq!(header, ("x-api-key".to_string(), "abc:123".to_string()));
+        assert!(parse_header("x-api-key").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_is_ipc_path() {
+        assert!(is_ipc_path("/tmp/pharos/node.ipc"));
+        assert!(is_ipc_path(r"\\.\pipe\geth"));
+        assert!(!is_ipc_path("http://localhost:8545"));
+        assert!(!is_ipc_path("ws://localhost:8546/node.ipc"));
+    }
+
+    #[test]
+    fn test_wait_with_timeout() -> Result<()> {
+        let mut child = Command::new("true").spawn()?;
+        let status = wait_with_timeout(&mut child, Duration::from_secs(10))?;
+        assert!(status.is_some_and(|s| s.success()));
+
+        let mut child = Command::new("sleep").arg("10").spawn()?;
+        assert_eq!(
+            wait_with_timeout(&mut child, Duration::from_millis(200))?,
+            None
+        );
+        Ok(())
+    }
+}
@@ -1,6 +1,7 @@
 // Copyright 2024, Offchain Labs, Inc.
 // For licensing, see https://github.com/OffchainLabs/cargo-stylus/blob/main/licenses/COPYRIGHT.md
 
+use crate::util::color::Color;
 use eyre::Result;
 
 pub fn decode0x<T: AsRef<str>>(text: T) -> Result<Vec<u8>> {
@@ -9,3 +10,21 @@ pub fn decode0x<T: AsRef<str>>(text: T) -> Result<Vec<u8>> {
     let text = text.strip_prefix("0x").unwrap_or(text);
     Ok(hex::decode(text)?)
 }
+
+/// Renders key-value rows as an aligned two-column block.
+pub fn format_table(rows: &[(&str, String)]) -> String {
+    let width = rows
+        .iter()
+        .map(|(key, _)| key.len())
+        .max()
+        .unwrap_or_default();
+    rows.iter()
+        .map(|(key, value)| {
+            format!(
+                "{}  {}\n",
+                format!("{key:<width$}").grey(),
+                value.lavender()
+            )
+        })
+        .collect()
+}
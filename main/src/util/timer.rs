@@ -0,0 +1,29 @@
+// Copyright 2024, Offchain Labs, Inc.
+// For licensing, see https://github.com/OffchainLabs/cargo-stylus/blob/main/licenses/COPYRIGHT.md
+
+use crate::{macros::greyln, util::color::Color};
+use std::time::Instant;
+
+/// Times consecutive steps of a command, printing how long each took when verbose.
+pub struct StepTimer {
+    verbose: bool,
+    start: Instant,
+}
+
+impl StepTimer {
+    pub fn start(verbose: bool) -> Self {
+        Self {
+            verbose,
+            start: Instant::now(),
+        }
+    }
+
+    /// Ends the current step, printing its duration, and starts timing the next one.
+    pub fn lap(&mut self, step: &str) {
+        let elapsed = self.start.elapsed();
+        if self.verbose {
+            greyln!("{step} took {}", format!("{elapsed:.2?}").lavender());
+        }
+        self.start = Instant::now();
+    }
+}
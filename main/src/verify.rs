@@ -10,24 +10,20 @@ use eyre::{bail, eyre};
 use ethers::middleware::Middleware;
 use ethers::types::H256;
 
-use serde::{Deserialize, Serialize};
-
 use crate::util::{color::Color, sys};
 use crate::{
-    check,
+    check, compression,
     constants::TOOLCHAIN_FILE_NAME,
-    deploy::{self, extract_compressed_wasm, extract_contract_evm_deployment_prelude},
+    prelude::{
+        self, extract_compressed_wasm, extract_compression, extract_constructor_args,
+        extract_contract_evm_deployment_prelude,
+    },
     project::{self, extract_toolchain_channel},
     CheckConfig, DataFeeOpts, VerifyConfig,
 };
 
-#[derive(Debug, Deserialize, Serialize)]
-struct RpcResult {
-    input: String,
-}
-
 pub async fn verify(cfg: VerifyConfig) -> eyre::Result<()> {
-    let provider = sys::new_provider(&cfg.common_cfg.endpoint)?;
+    let provider = cfg.common_cfg.new_provider().await?;
     let hash = crate::util::text::decode0x(cfg.deployment_tx)?;
     if hash.len() != 32 {
         bail!("Invalid hash");
@@ -57,21 +53,49 @@ pub async fn verify(cfg: VerifyConfig) -> eyre::Result<()> {
         },
         wasm_file: None,
         contract_address: None,
+        optimize: false,
+        max_code_size_bytes: None,
+        max_functions: None,
+        no_project_hash: false,
+        size_log: None,
+        size_diff: false,
+        sarif: None,
+        strip: true,
+        auto_install_toolchain: false,
+        size_only: false,
+        calldata_out: None,
+        wasm_out: None,
+        compressed_out: None,
+        prelude_out: None,
+        raw_code: false,
     };
     let _ = check::check(&check_cfg)
         .await
         .map_err(|e| eyre!("Stylus checks failed: {e}"))?;
+    // Recompress with the scheme signaled by the deployment prelude so the
+    // comparison does not depend on the local --compression flag.
+    let compression = extract_compression(&result.input).unwrap_or(cfg.common_cfg.compression);
     let build_cfg = project::BuildConfig {
         opt_level: project::OptLevel::default(),
         stable: rust_stable,
         features: cfg.common_cfg.features.clone(),
+        compression,
+        profile: cfg.common_cfg.profile.clone(),
+        rustflags: cfg.common_cfg.rustflags.clone(),
+        fail_on_warnings: cfg.common_cfg.fail_on_warnings,
+        bin: cfg.common_cfg.bin.clone(),
+        quiet: false,
     };
     let wasm_file: PathBuf = project::build_dylib(build_cfg.clone())
         .map_err(|e| eyre!("could not build project to WASM: {e}"))?;
     let project_hash =
         project::hash_project(cfg.common_cfg.source_files_for_project_hash, build_cfg)?;
-    let (_, init_code) = project::compress_wasm(&wasm_file, project_hash)?;
-    let deployment_data = deploy::contract_deployment_calldata(&init_code);
+    let (wasm, init_code) =
+        compression::compress_wasm(&wasm_file, Some(project_hash), compression, check_cfg.strip)?;
+    // Match how the tx was deployed, with or without the prelude.
+    let raw = !prelude::has_deployment_prelude(&result.input);
+    let mut deployment_data = prelude::deployment_calldata(&init_code, compression, raw)?;
+    deployment_data.extend(extract_constructor_args(&result.input));
     if deployment_data == *result.input {
         println!("Verified - contract matches local project's file hashes");
     } else {
@@ -90,6 +114,14 @@ pub async fn verify(cfg: VerifyConfig) -> eyre::Result<()> {
             );
         } else {
             println!("Compressed WASM bytecode mismatch");
+            let tx_code = extract_compressed_wasm(&result.input);
+            match compression::decompress_wasm(&tx_code, compression) {
+                Ok(tx_wasm) if tx_wasm == wasm => {
+                    println!("Decompressed WASM matches, only the {compression} output differs")
+                }
+                Ok(_) => println!("Decompressed WASM bytecode mismatch"),
+                Err(e) => println!("Could not decompress deployment tx WASM: {e}"),
+            }
         }
         println!(
             "Compressed code length of locally reconstructed {}",
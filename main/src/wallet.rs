@@ -1,18 +1,96 @@
 // Copyright 2023-2024, Offchain Labs, Inc.
 // For licensing, see https://github.com/OffchainLabs/cargo-stylus/blob/main/licenses/COPYRIGHT.md
 
-use crate::util::text::{self};
-use crate::AuthOpts;
-use ethers::signers::LocalWallet;
+use crate::{
+    error::StylusError,
+    keychain,
+    macros::*,
+    util::{color::Color, sys},
+    AuthOpts,
+};
+use async_trait::async_trait;
+use ethers::{
+    providers::{Middleware, Provider},
+    signers::{
+        coins_bip39::English, AwsSigner, AwsSignerError, HDPath, Ledger, LedgerError, LocalWallet,
+        MnemonicBuilder, Signer, Trezor, TrezorError, TrezorHDPath, WalletError,
+    },
+    types::{
+        transaction::{eip2718::TypedTransaction, eip712::Eip712},
+        Address, Bytes, Signature,
+    },
+    utils::rlp::Rlp,
+};
 use eyre::{eyre, Context, Result};
-use std::fs;
+use rusoto_core::{HttpClient, Region};
+use rusoto_credential::{ChainProvider, ProfileProvider};
+use rusoto_kms::KmsClient;
+use std::{fs, str::FromStr};
 
 /// Loads a wallet for signing transactions.
 impl AuthOpts {
+    /// Loads a signer, connecting to a hardware wallet if one was requested.
+    pub async fn signer(&self) -> Result<StylusSigner> {
+        if self.ledger {
+            let path = match &self.hd_path {
+                Some(path) => HDPath::Other(path.clone()),
+                None => HDPath::LedgerLive(self.account_index as usize),
+            };
+            greyln!("connecting to Ledger, please unlock it and open the Ethereum app");
+            // The chain id is set once the provider is known, see `Signer::with_chain_id`.
+            let ledger = Ledger::new(path, 1)
+                .await
+                .wrap_err("failed to connect to Ledger")?;
+            return Ok(StylusSigner::Ledger(ledger));
+        }
+        if self.trezor {
+            let path = match &self.hd_path {
+                Some(path) => TrezorHDPath::Other(path.clone()),
+                None => TrezorHDPath::TrezorLive(self.account_index as usize),
+            };
+            greyln!("connecting to Trezor, please unlock it");
+            let trezor = Trezor::new(path, 1, None)
+                .await
+                .wrap_err("failed to connect to Trezor")?;
+            return Ok(StylusSigner::Trezor(trezor));
+        }
+        if let Some(url) = &self.remote_signer_url {
+            let signer = RemoteSigner::connect(url, self.remote_signer_address).await?;
+            return Ok(StylusSigner::Remote(signer));
+        }
+        if let Some(key_id) = &self.aws_kms_key_id {
+            let signer = AwsSigner::new(self.kms_client()?, key_id, 1)
+                .await
+                .wrap_err("failed to load AWS KMS key")?;
+            return Ok(StylusSigner::Aws(signer));
+        }
+        Ok(StylusSigner::Local(self.wallet()?))
+    }
+
+    /// Connects to KMS in the configured region, with credentials from the named profile
+    /// or the usual AWS environment, profile and instance metadata chain.
+    fn kms_client(&self) -> Result<KmsClient> {
+        let region = match &self.aws_region {
+            Some(region) => Region::from_str(region).wrap_err("invalid AWS region")?,
+            None => Region::default(),
+        };
+        let http = HttpClient::new().wrap_err("failed to create AWS http client")?;
+        let client = match &self.aws_profile {
+            Some(profile) => {
+                let mut provider =
+                    ProfileProvider::new().wrap_err("failed to load AWS profiles")?;
+                provider.set_profile(profile);
+                KmsClient::new_with(http, provider, region)
+            }
+            None => KmsClient::new_with(http, ChainProvider::new(), region),
+        };
+        Ok(client)
+    }
+
     pub fn wallet(&self) -> Result<LocalWallet> {
         macro_rules! wallet {
             ($key:expr) => {{
-                let key = text::decode0x($key).wrap_err("invalid private key")?;
+                let key = decode_private_key(&$key)?;
                 LocalWallet::from_bytes(&key).wrap_err("invalid private key")
             }};
         }
@@ -29,13 +107,275 @@ impl AuthOpts {
             return wallet!(key);
         }
 
+        if let Some(name) = &self.keychain_key {
+            return wallet!(keychain::load(name)?);
+        }
+
+        let phrase = match &self.mnemonic_path {
+            Some(file) => Some(fs::read_to_string(file).wrap_err("could not open mnemonic file")?),
+            None => self.mnemonic.clone(),
+        };
+        if let Some(phrase) = phrase {
+            return mnemonic_wallet(&phrase, self.hd_path.as_deref(), self.account_index);
+        }
+
         let keystore = self.keystore_path.as_ref().ok_or(eyre!("no keystore"))?;
-        let password = self
-            .keystore_password_path
-            .as_ref()
-            .map(fs::read_to_string)
-            .unwrap_or(Ok("".into()))?;
+        let password = match &self.keystore_password_path {
+            Some(path) => fs::read_to_string(path).wrap_err("could not open password file")?,
+            None => rpassword::prompt_password("keystore password: ")
+                .wrap_err("failed to read keystore password")?,
+        };
 
         LocalWallet::decrypt_keystore(keystore, password).wrap_err("could not decrypt keystore")
     }
 }
+
+/// A transaction signer backed by either a local key or a hardware wallet.
+#[derive(Debug)]
+pub enum StylusSigner {
+    Local(LocalWallet),
+    Ledger(Ledger),
+    Trezor(Trezor),
+    Aws(AwsSigner),
+    Remote(RemoteSigner),
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum StylusSignerError {
+    #[error(transparent)]
+    Local(#[from] WalletError),
+    #[error(transparent)]
+    Ledger(#[from] LedgerError),
+    #[error(transparent)]
+    Trezor(#[from] TrezorError),
+    #[error(transparent)]
+    Aws(#[from] AwsSignerError),
+    #[error("remote signer: {0}")]
+    Remote(String),
+}
+
+#[async_trait]
+impl Signer for StylusSigner {
+    type Error = StylusSignerError;
+
+    async fn sign_message<S: Send + Sync + AsRef<[u8]>>(
+        &self,
+        message: S,
+    ) -> Result<Signature, Self::Error> {
+        match self {
+            Self::Local(wallet) => Ok(wallet.sign_message(message).await?),
+            Self::Ledger(ledger) => {
+                greyln!("please confirm the message on your Ledger device");
+                Ok(ledger.sign_message(message).await?)
+            }
+            Self::Trezor(trezor) => {
+                greyln!("please confirm the message on your Trezor device");
+                Ok(trezor.sign_message(message).await?)
+            }
+            Self::Aws(signer) => Ok(signer.sign_message(message).await?),
+            Self::Remote(signer) => signer.sign_message(message.as_ref()).await,
+        }
+    }
+
+    async fn sign_transaction(&self, tx: &TypedTransaction) -> Result<Signature, Self::Error> {
+        match self {
+            Self::Local(wallet) => Ok(wallet.sign_transaction(tx).await?),
+            Self::Ledger(ledger) => {
+                greyln!("please confirm the transaction on your Ledger device");
+                Ok(ledger.sign_transaction(tx).await?)
+            }
+            Self::Trezor(trezor) => {
+                greyln!("please confirm the transaction on your Trezor device");
+                Ok(trezor.sign_transaction(tx).await?)
+            }
+            Self::Aws(signer) => Ok(signer.sign_transaction(tx).await?),
+            Self::Remote(signer) => signer.sign_transaction(tx).await,
+        }
+    }
+
+    async fn sign_typed_data<T: Eip712 + Send + Sync>(
+        &self,
+        payload: &T,
+    ) -> Result<Signature, Self::Error> {
+        match self {
+            Self::Local(wallet) => Ok(wallet.sign_typed_data(payload).await?),
+            Self::Ledger(ledger) => {
+                greyln!("please confirm the typed data on your Ledger device");
+                Ok(ledger.sign_typed_data(payload).await?)
+            }
+            Self::Trezor(trezor) => {
+                greyln!("please confirm the typed data on your Trezor device");
+                Ok(trezor.sign_typed_data(payload).await?)
+            }
+            Self::Aws(signer) => Ok(signer.sign_typed_data(payload).await?),
+            Self::Remote(_) => Err(StylusSignerError::Remote(
+                "signing typed data is not supported".into(),
+            )),
+        }
+    }
+
+    fn address(&self) -> Address {
+        match self {
+            Self::Local(wallet) => wallet.address(),
+            Self::Ledger(ledger) => ledger.address(),
+            Self::Trezor(trezor) => trezor.address(),
+            Self::Aws(signer) => signer.address(),
+            Self::Remote(signer) => signer.address,
+        }
+    }
+
+    fn chain_id(&self) -> u64 {
+        match self {
+            Self::Local(wallet) => wallet.chain_id(),
+            Self::Ledger(ledger) => ledger.chain_id(),
+            Self::Trezor(trezor) => trezor.chain_id(),
+            Self::Aws(signer) => signer.chain_id(),
+            Self::Remote(signer) => signer.chain_id,
+        }
+    }
+
+    fn with_chain_id<T: Into<u64>>(self, chain_id: T) -> Self {
+        match self {
+            Self::Local(wallet) => Self::Local(wallet.with_chain_id(chain_id)),
+            Self::Ledger(ledger) => Self::Ledger(ledger.with_chain_id(chain_id)),
+            Self::Trezor(trezor) => Self::Trezor(trezor.with_chain_id(chain_id)),
+            Self::Aws(signer) => Self::Aws(signer.with_chain_id(chain_id)),
+            Self::Remote(signer) => Self::Remote(RemoteSigner {
+                chain_id: chain_id.into(),
+                ..signer
+            }),
+        }
+    }
+}
+
+/// Signs through a Web3Signer-compatible service over JSON-RPC, so keys stay in its custody.
+/// Transactions are sent to it unsigned and the signature is taken from the raw
+/// transaction it returns, which is then broadcast as usual.
+#[derive(Debug)]
+pub struct RemoteSigner {
+    provider: Provider<sys::RpcClient>,
+    address: Address,
+    chain_id: u64,
+}
+
+impl RemoteSigner {
+    /// Connects to the signer, signing as the address or else its first account.
+    async fn connect(url: &str, address: Option<Address>) -> Result<Self> {
+        let provider = sys::new_provider(url).await?;
+        let address = match address {
+            Some(address) => address,
+            None => {
+                let accounts = provider
+                    .get_accounts()
+                    .await
+                    .wrap_err("failed to list remote signer accounts")?;
+                *accounts
+                    .first()
+                    .ok_or_else(|| eyre!("remote signer has no accounts"))?
+            }
+        };
+        Ok(Self {
+            provider,
+            address,
+            chain_id: 1,
+        })
+    }
+
+    async fn sign_message(&self, message: &[u8]) -> Result<Signature, StylusSignerError> {
+        let message = Bytes::from(message.to_vec());
+        let signature: Bytes = self
+            .provider
+            .request("eth_sign", (self.address, message))
+            .await
+            .map_err(remote_error)?;
+        Signature::try_from(signature.as_ref()).map_err(remote_error)
+    }
+
+    async fn sign_transaction(
+        &self,
+        tx: &TypedTransaction,
+    ) -> Result<Signature, StylusSignerError> {
+        let mut tx = tx.clone();
+        tx.set_from(self.address);
+        if tx.chain_id().is_none() {
+            tx.set_chain_id(self.chain_id);
+        }
+        let raw: Bytes = self
+            .provider
+            .request("eth_signTransaction", [tx])
+            .await
+            .map_err(remote_error)?;
+        let (_, signature) =
+            TypedTransaction::decode_signed(&Rlp::new(&raw)).map_err(remote_error)?;
+        Ok(signature)
+    }
+}
+
+fn remote_error(err: impl std::fmt::Display) -> StylusSignerError {
+    StylusSignerError::Remote(err.to_string())
+}
+
+/// Derives a wallet from a mnemonic, at the given path or else at the account index on
+/// the standard Ethereum path.
+fn mnemonic_wallet(phrase: &str, hd_path: Option<&str>, index: u32) -> Result<LocalWallet> {
+    let builder = MnemonicBuilder::<English>::default().phrase(phrase.trim());
+    let builder = match hd_path {
+        Some(path) => builder.derivation_path(path)?,
+        None => builder.index(index)?,
+    };
+    builder.build().wrap_err("invalid mnemonic")
+}
+
+/// Decodes a hex-encoded private key, with or without a `0x` prefix.
+pub(crate) fn decode_private_key(key: &str) -> Result<Vec<u8>> {
+    let key = key.trim();
+    let key = key.strip_prefix("0x").unwrap_or(key);
+    let key = hex::decode(key).map_err(|e| {
+        StylusError::Validation(format!(
+            "invalid private key: expected 32-byte hex, got {e}"
+        ))
+    })?;
+    if key.len() != 32 {
+        let msg = format!(
+            "invalid private key: expected 32-byte hex, got {} bytes",
+            key.len()
+        );
+        return Err(StylusError::Validation(msg).into());
+    }
+    Ok(key)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const KEY: &str = "4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318";
+
+    #[test]
+    fn test_decode_private_key() -> Result<()> {
+        let expected = hex::decode(KEY)?;
+        assert_eq!(decode_private_key(KEY)?, expected);
+        assert_eq!(decode_private_key(&format!("0x{KEY}"))?, expected);
+        assert_eq!(decode_private_key(&format!(" 0x{KEY}\n"))?, expected);
+
+        let err = decode_private_key(&KEY[..62]).unwrap_err();
+        assert!(err.to_string().contains("got 31 bytes"));
+
+        let err = decode_private_key(&KEY.replace('4', "z")).unwrap_err();
+        assert!(err.to_string().contains("invalid private key"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_mnemonic_wallet() -> Result<()> {
+        let phrase = "test test test test test test test test test test test junk\n";
+        let first: Address = "0xf39Fd6e51aad88F6F4ce6aB8827279cffFb92266".parse()?;
+        let second: Address = "0x70997970C51812dc3A010C7d01b50e0d17dc79C8".parse()?;
+        assert_eq!(mnemonic_wallet(phrase, None, 0)?.address(), first);
+        assert_eq!(mnemonic_wallet(phrase, None, 1)?.address(), second);
+        let path = "m/44'/60'/0'/0/1";
+        assert_eq!(mnemonic_wallet(phrase, Some(path), 0)?.address(), second);
+        assert!(mnemonic_wallet("not a mnemonic", None, 0).is_err());
+        Ok(())
+    }
+}